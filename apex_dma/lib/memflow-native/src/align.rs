@@ -0,0 +1,148 @@
+use memflow::prelude::v1::*;
+
+const PAGE_SIZE: umem = 0x1000;
+
+/// Reads each request as the whole pages it touches, passing the requested bytes on.
+///
+/// Mappings and protections are page granular, so the aligned span never reaches a page the
+/// request does not touch already, the span is only clamped to the end of the address space.
+/// Spans that fail are read again exactly as requested, so requests that are partially readable
+/// end up in the same callback as without alignment.
+pub(crate) fn aligned_read(
+    mem: &mut impl MemoryView,
+    MemOps {
+        inp,
+        mut out,
+        mut out_fail,
+    }: ReadRawMemOps,
+) -> Result<()> {
+    let ops = inp.collect::<Vec<_>>();
+
+    let mut spans = ops
+        .iter()
+        .map(|CTup3(addr, _, buf)| {
+            let start = addr.to_umem() & !(PAGE_SIZE - 1);
+            let end = addr.to_umem().saturating_add(buf.len() as umem);
+            let end = end
+                .checked_add(PAGE_SIZE - 1)
+                .map_or(umem::MAX, |end| end & !(PAGE_SIZE - 1));
+
+            (Address::from(start), vec![0u8; (end - start) as usize])
+        })
+        .collect::<Vec<_>>();
+
+    let mut failed = vec![];
+
+    let fail = &mut |CTup2(addr, data): ReadData| {
+        failed.push((addr, addr + data.len()));
+        true
+    };
+
+    mem.read_iter(
+        spans
+            .iter_mut()
+            .map(|(start, span)| CTup2(*start, span.as_mut_slice().into())),
+        None,
+        Some(&mut fail.into()),
+    )?;
+
+    for (CTup3(addr, meta_addr, mut buf), (start, span)) in ops.into_iter().zip(spans) {
+        let end = addr + buf.len();
+
+        let ok = if failed
+            .iter()
+            .any(|&(f_start, f_end)| f_start < end && f_end > addr)
+        {
+            mem.read_raw_into(addr, &mut buf).is_ok()
+        } else {
+            let offset = (addr - start) as usize;
+            let len = buf.len();
+            buf.copy_from_slice(&span[offset..offset + len]);
+            true
+        };
+
+        let keep_going = if ok {
+            opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+        } else {
+            opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+        };
+
+        if !keep_going {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::MockMemory;
+
+    #[test]
+    fn reads_whole_pages() {
+        let mut mem = MockMemory::new(0x3000, None);
+        mem.data[0x1ffc..0x2004].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let mut buf = [0u8; 8];
+        mem.read_raw_into(Address::from(0x1ffc), &mut buf).unwrap();
+        mem.reads.clear();
+
+        let mut aligned = [0u8; 8];
+        let ops = [CTup3(
+            Address::from(0x1ffc),
+            Address::from(0x1ffc),
+            aligned.as_mut_slice().into(),
+        )]
+        .into_iter();
+        MemOps::with_raw(ops, None, None, |ops| aligned_read(&mut mem, ops)).unwrap();
+
+        assert_eq!(aligned, buf);
+        assert_eq!(mem.reads, [Address::from(0x1000)].to_vec());
+    }
+
+    #[test]
+    fn failed_spans_are_read_as_requested() {
+        let mut mem = MockMemory::new(0x3000, Some(Address::from(0x2800)));
+        mem.data[0x1010..0x1014].copy_from_slice(&[1, 2, 3, 4]);
+        mem.data[0x1ffe..0x2002].copy_from_slice(&[5, 6, 7, 8]);
+
+        let mut bufs = [[0u8; 4]; 3];
+        let mut read = vec![];
+        let mut failed = vec![];
+
+        let ok = &mut |CTup2(addr, _): ReadData| {
+            read.push(addr);
+            true
+        };
+        let fail = &mut |CTup2(addr, _): ReadData| {
+            failed.push(addr);
+            true
+        };
+
+        let ops = [0x1010, 0x1ffe, 0x27fe]
+            .into_iter()
+            .zip(bufs.iter_mut())
+            .map(|(addr, buf)| {
+                CTup3(
+                    Address::from(addr),
+                    Address::from(addr),
+                    buf.as_mut_slice().into(),
+                )
+            });
+
+        MemOps::with_raw(ops, Some(&mut ok.into()), Some(&mut fail.into()), |ops| {
+            aligned_read(&mut mem, ops)
+        })
+        .unwrap();
+
+        // The second read shares the failing page with the third but does not reach the failing
+        // address, so it succeeds once it is read exactly as requested.
+        assert_eq!(read, [0x1010, 0x1ffe].map(Address::from).to_vec());
+        assert_eq!(failed, [Address::from(0x27fe)].to_vec());
+        assert_eq!(bufs[0], [1, 2, 3, 4]);
+        assert_eq!(bufs[1], [5, 6, 7, 8]);
+    }
+}
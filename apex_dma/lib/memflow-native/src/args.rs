@@ -0,0 +1,242 @@
+use memflow::prelude::v1::*;
+
+#[cfg(target_os = "windows")]
+use std::time::Duration;
+
+/// Parses a boolean value of an extra argument.
+///
+/// The value is trimmed and matched case-insensitively.
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "y" | "1" => Some(true),
+        "off" | "false" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Retrieves a boolean extra argument, falling back to `default` when it is not set.
+pub(crate) fn bool_arg(args: &Args, name: &str, default: bool) -> Result<bool> {
+    match args.get(name) {
+        Some(value) => parse_bool(value).ok_or_else(|| {
+            Error(ErrorOrigin::Args, ErrorKind::ArgValidation)
+                .log_error(format!("invalid boolean value for `{name}`: {value}"))
+        }),
+        None => Ok(default),
+    }
+}
+
+/// Parses a size value of an extra argument.
+///
+/// Accepts decimal or `0x` prefixed hex numbers with an optional binary `k`, `m` or `g` suffix,
+/// so `256m`, `256MiB` and `0x10000000` are all the same size. A trailing `b` only counts as a
+/// unit after a decimal number or a suffix, as `0x1b` is the hex number.
+pub(crate) fn parse_size(value: &str) -> Option<usize> {
+    let value = value.trim().to_ascii_lowercase();
+
+    let (digits, radix) = match value.strip_prefix("0x") {
+        Some(hex) => (hex, 16),
+        None => (value.as_str(), 10),
+    };
+
+    let digits = digits
+        .strip_suffix("ib")
+        .or_else(|| digits.strip_suffix('b'))
+        .filter(|rest| radix == 10 || rest.ends_with(['k', 'm', 'g']))
+        .unwrap_or(digits);
+
+    let (num, shift) = match digits.as_bytes().last()? {
+        b'k' => (&digits[..digits.len() - 1], 10),
+        b'm' => (&digits[..digits.len() - 1], 20),
+        b'g' => (&digits[..digits.len() - 1], 30),
+        _ => (digits, 0),
+    };
+
+    usize::from_str_radix(num, radix)
+        .ok()?
+        .checked_mul(1 << shift)
+}
+
+/// Retrieves a size extra argument, falling back to `default` when it is not set.
+pub(crate) fn size_arg(args: &Args, name: &str, default: usize) -> Result<usize> {
+    match args.get(name) {
+        Some(value) => parse_size(value).ok_or_else(|| {
+            Error(ErrorOrigin::Args, ErrorKind::ArgValidation)
+                .log_error(format!("invalid size value for `{name}`: {value}"))
+        }),
+        None => Ok(default),
+    }
+}
+
+/// Retrieves a duration extra argument given in milliseconds, `None` when it is not set or zero.
+#[cfg(target_os = "windows")]
+pub(crate) fn millis_arg(args: &Args, name: &str) -> Result<Option<Duration>> {
+    match args.get(name) {
+        Some(value) => match value.trim().parse::<u64>() {
+            Ok(0) => Ok(None),
+            Ok(millis) => Ok(Some(Duration::from_millis(millis))),
+            Err(_) => Err(Error(ErrorOrigin::Args, ErrorKind::ArgValidation)
+                .log_error(format!("invalid millisecond value for `{name}`: {value}"))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Retrieves a comma separated list extra argument, falling back to `default` when it is not set.
+///
+/// The value has to be quoted to keep its commas from separating arguments, as in
+/// `privileges="SeDebugPrivilege,SeLoadDriverPrivilege"`. Empty entries are dropped.
+#[cfg(target_os = "windows")]
+pub(crate) fn list_arg(args: &Args, name: &str, default: &[&str]) -> Vec<String> {
+    match args.get(name) {
+        Some(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(String::from)
+            .collect(),
+        None => default.iter().map(|&entry| entry.into()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_accepts_decimal_and_hex() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size(" 4096 "), Some(4096));
+        assert_eq!(parse_size("0x1000"), Some(0x1000));
+        assert_eq!(parse_size("0X1000"), Some(0x1000));
+        assert_eq!(parse_size("0"), Some(0));
+    }
+
+    #[test]
+    fn parse_size_keeps_trailing_hex_digits() {
+        assert_eq!(parse_size("0x1b"), Some(0x1b));
+        assert_eq!(parse_size("0xb"), Some(0xb));
+        assert_eq!(parse_size("0xab"), Some(0xab));
+    }
+
+    #[test]
+    fn parse_size_applies_suffixes() {
+        assert_eq!(parse_size("4k"), Some(4 << 10));
+        assert_eq!(parse_size("256m"), Some(256 << 20));
+        assert_eq!(parse_size("1g"), Some(1 << 30));
+        assert_eq!(parse_size("256MiB"), Some(256 << 20));
+        assert_eq!(parse_size("4kb"), Some(4 << 10));
+        assert_eq!(parse_size("4KiB"), Some(4 << 10));
+        assert_eq!(parse_size("16b"), Some(16));
+        assert_eq!(parse_size("0x10m"), Some(0x10 << 20));
+        assert_eq!(parse_size("0x10kib"), Some(0x10 << 10));
+    }
+
+    #[test]
+    fn parse_size_rejects_overflow() {
+        assert_eq!(parse_size(&format!("{}g", usize::MAX)), None);
+        assert_eq!(parse_size(&format!("{}0", usize::MAX)), None);
+        assert_eq!(parse_size("0x1ffffffffffffffffffff"), None);
+    }
+
+    #[test]
+    fn parse_size_rejects_garbage() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("k"), None);
+        assert_eq!(parse_size("0x"), None);
+        assert_eq!(parse_size("0xkb"), None);
+        assert_eq!(parse_size("12q"), None);
+        assert_eq!(parse_size("1.5g"), None);
+        assert_eq!(parse_size("-1"), None);
+        assert_eq!(parse_size("big"), None);
+    }
+
+    #[test]
+    fn parse_bool_accepts_common_spellings() {
+        for value in ["on", "true", "yes", "y", "1", " TRUE ", "Yes"] {
+            assert_eq!(parse_bool(value), Some(true), "{value}");
+        }
+
+        for value in ["off", "false", "no", "n", "0", " Off ", "NO"] {
+            assert_eq!(parse_bool(value), Some(false), "{value}");
+        }
+
+        for value in ["", "2", "maybe", "enabled", "t"] {
+            assert_eq!(parse_bool(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn bool_arg_falls_back_to_default() {
+        let args = Args::new().insert("cache", "off");
+
+        assert!(!bool_arg(&args, "cache", true).unwrap());
+        assert!(bool_arg(&args, "page_align", true).unwrap());
+        assert!(!bool_arg(&args, "page_align", false).unwrap());
+    }
+
+    #[test]
+    fn bool_arg_rejects_invalid_values() {
+        let args = Args::new().insert("cache", "sometimes");
+
+        assert_eq!(
+            bool_arg(&args, "cache", true),
+            Err(Error(ErrorOrigin::Args, ErrorKind::ArgValidation))
+        );
+    }
+
+    #[test]
+    fn size_arg_falls_back_to_default() {
+        let args = Args::new().insert("max_read_size", "16m");
+
+        assert_eq!(size_arg(&args, "max_read_size", 0), Ok(16 << 20));
+        assert_eq!(size_arg(&args, "min_address", 0x1000), Ok(0x1000));
+    }
+
+    #[test]
+    fn size_arg_rejects_invalid_values() {
+        let args = Args::new().insert("max_read_size", "lots");
+
+        assert_eq!(
+            size_arg(&args, "max_read_size", 0),
+            Err(Error(ErrorOrigin::Args, ErrorKind::ArgValidation))
+        );
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn millis_arg_treats_zero_as_unset() {
+        let args = Args::new()
+            .insert("timeout", "250")
+            .insert("zero", "0")
+            .insert("bad", "1.5");
+
+        assert_eq!(
+            millis_arg(&args, "timeout"),
+            Ok(Some(Duration::from_millis(250)))
+        );
+        assert_eq!(millis_arg(&args, "zero"), Ok(None));
+        assert_eq!(millis_arg(&args, "missing"), Ok(None));
+        assert_eq!(
+            millis_arg(&args, "bad"),
+            Err(Error(ErrorOrigin::Args, ErrorKind::ArgValidation))
+        );
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn list_arg_splits_and_trims_entries() {
+        let args = Args::new()
+            .insert("privileges", " SeDebugPrivilege, ,SeLoadDriverPrivilege,")
+            .insert("empty", "");
+
+        assert_eq!(
+            list_arg(&args, "privileges", &[]),
+            ["SeDebugPrivilege", "SeLoadDriverPrivilege"]
+        );
+        assert!(list_arg(&args, "empty", &["SeDebugPrivilege"]).is_empty());
+        assert_eq!(
+            list_arg(&args, "missing", &["SeDebugPrivilege"]),
+            ["SeDebugPrivilege"]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use memflow::prelude::v1::*;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Upper bound for cached pages (64 MiB), stale pages are evicted once it is reached.
+const MAX_PAGES: usize = 0x4000;
+
+/// A time based cache of whole pages sitting in front of a process' memory.
+///
+/// Cached pages are served until they are older than the configured time to live. Writes going
+/// through the cache drop the pages they touch, any other change in the target has to be
+/// signalled with [`invalidate`](Self::invalidate) or [`invalidate_range`](Self::invalidate_range).
+#[derive(Clone)]
+pub(crate) struct PageCache {
+    ttl: Duration,
+    pages: HashMap<umem, (Instant, Box<[u8]>)>,
+}
+
+impl PageCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            pages: HashMap::new(),
+        }
+    }
+
+    /// Time to live of cached pages.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Drops all cached pages.
+    pub fn invalidate(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Drops the cached pages overlapping `start..end`.
+    pub fn invalidate_range(&mut self, start: Address, end: Address) {
+        if end <= start {
+            return;
+        }
+
+        let first = start.as_page_aligned(PAGE_SIZE);
+        let page_count =
+            ((end - first) as umem).saturating_add(PAGE_SIZE as umem - 1) / PAGE_SIZE as umem;
+
+        if page_count < self.pages.len() as umem {
+            (0..page_count).for_each(|i| {
+                self.pages
+                    .remove(&(first + i * PAGE_SIZE as umem).to_umem());
+            });
+        } else {
+            self.pages.retain(|&page, _| {
+                page + PAGE_SIZE as umem <= start.to_umem() || page >= end.to_umem()
+            });
+        }
+    }
+
+    /// Serves the reads from cached pages, fetching whole pages from `mem` on a miss.
+    ///
+    /// Chunks whose page can not be read in full are read directly instead, so partially
+    /// readable pages never end up in the cache.
+    pub fn read(
+        &mut self,
+        mem: &mut impl MemoryView,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, buf) in inp {
+            let mut addr = addr;
+            let mut meta_addr = meta_addr;
+            let mut rest = Some(buf);
+
+            // Split the request along page boundaries.
+            while let Some(buf) = rest.take() {
+                let page = addr.as_page_aligned(PAGE_SIZE);
+                let offset = (addr - page) as usize;
+
+                let (head, tail) = buf.split_at((PAGE_SIZE - offset) as umem);
+                rest = tail;
+
+                if let Some(mut head) = head {
+                    let len = head.len();
+
+                    let ok = match self.page(mem, page) {
+                        Some(data) => {
+                            head.copy_from_slice(&data[offset..offset + len]);
+                            true
+                        }
+                        None => mem.read_raw_into(addr, &mut head).is_ok(),
+                    };
+
+                    let keep_going = if ok {
+                        opt_call(out.as_deref_mut(), CTup2(meta_addr, head))
+                    } else {
+                        opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, head))
+                    };
+
+                    if !keep_going {
+                        return Ok(());
+                    }
+
+                    addr += len;
+                    meta_addr += len;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes through to `mem`, dropping the cached pages that are written to.
+    pub fn write(&mut self, mem: &mut impl MemoryView, ops: WriteRawMemOps) -> Result<()> {
+        let MemOps { inp, out, out_fail } = ops;
+
+        let iter = inp.inspect(|CTup3(addr, _, data)| {
+            self.invalidate_range(*addr, *addr + data.len());
+        });
+
+        MemOps::with_raw(iter, out, out_fail, |data| mem.write_raw_iter(data))
+    }
+
+    /// Returns the cached page at `page`, reading it from `mem` if it is missing or stale.
+    fn page(&mut self, mem: &mut impl MemoryView, page: Address) -> Option<&[u8]> {
+        let now = Instant::now();
+
+        let fresh = matches!(
+            self.pages.get(&page.to_umem()),
+            Some((time, _)) if now.duration_since(*time) < self.ttl
+        );
+
+        if !fresh {
+            let mut data = vec![0; PAGE_SIZE].into_boxed_slice();
+
+            if mem.read_raw_into(page, &mut data).is_err() {
+                self.pages.remove(&page.to_umem());
+                return None;
+            }
+
+            if self.pages.len() >= MAX_PAGES {
+                let ttl = self.ttl;
+                self.pages
+                    .retain(|_, (time, _)| now.duration_since(*time) < ttl);
+
+                if self.pages.len() >= MAX_PAGES {
+                    self.pages.clear();
+                }
+            }
+
+            self.pages.insert(page.to_umem(), (now, data));
+        }
+
+        self.pages.get(&page.to_umem()).map(|(_, data)| &data[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::MockMemory;
+
+    const TTL: Duration = Duration::from_secs(3600);
+
+    /// Reads `buf` at `addr` through the cache, returning whether the read succeeded.
+    fn read(cache: &mut PageCache, mem: &mut MockMemory, addr: Address, buf: &mut [u8]) -> bool {
+        let mut ok = true;
+        let fail = &mut |_| {
+            ok = false;
+            true
+        };
+
+        let ops = [CTup3(addr, addr, buf.into())].into_iter();
+        MemOps::with_raw(ops, None, Some(&mut fail.into()), |ops| {
+            cache.read(mem, ops)
+        })
+        .unwrap();
+
+        ok
+    }
+
+    #[test]
+    fn reads_are_served_from_cached_pages() {
+        let mut mem = MockMemory::new(0x3000, None);
+        mem.data[0x1ffc..0x2004].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let mut cache = PageCache::new(TTL);
+        let mut buf = [0u8; 8];
+
+        assert!(read(&mut cache, &mut mem, Address::from(0x1ffc), &mut buf));
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // Both pages are fetched whole once, after which the target is not read again.
+        assert!(read(&mut cache, &mut mem, Address::from(0x1ffc), &mut buf));
+        assert!(read(&mut cache, &mut mem, Address::from(0x1000), &mut buf));
+        assert_eq!(mem.reads, [0x1000, 0x2000].map(Address::from).to_vec());
+    }
+
+    #[test]
+    fn partially_readable_pages_are_not_cached() {
+        let mut mem = MockMemory::new(0x3000, Some(Address::from(0x1800)));
+        mem.data[0x1000..0x1004].copy_from_slice(&[1, 2, 3, 4]);
+
+        let mut cache = PageCache::new(TTL);
+        let mut buf = [0u8; 4];
+
+        assert!(read(&mut cache, &mut mem, Address::from(0x1000), &mut buf));
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert!(!read(&mut cache, &mut mem, Address::from(0x17fe), &mut buf));
+        assert!(cache.pages.is_empty());
+    }
+
+    #[test]
+    fn writes_drop_the_pages_they_touch() {
+        let mut mem = MockMemory::new(0x3000, None);
+
+        let mut cache = PageCache::new(TTL);
+        let mut buf = [0u8; 4];
+
+        assert!(read(&mut cache, &mut mem, Address::from(0x1000), &mut buf));
+        assert!(read(&mut cache, &mut mem, Address::from(0x2000), &mut buf));
+
+        let ops = [CTup3(
+            Address::from(0x1002),
+            Address::from(0x1002),
+            [9u8; 4].as_slice().into(),
+        )]
+        .into_iter();
+        MemOps::with_raw(ops, None, None, |ops| cache.write(&mut mem, ops)).unwrap();
+
+        assert!(!cache.pages.contains_key(&0x1000));
+        assert!(cache.pages.contains_key(&0x2000));
+
+        assert!(read(&mut cache, &mut mem, Address::from(0x1000), &mut buf));
+        assert_eq!(buf, [0, 0, 9, 9]);
+    }
+
+    #[test]
+    fn pages_are_evicted_at_max_pages() {
+        let mut mem = MockMemory::new((MAX_PAGES + 1) * PAGE_SIZE, None);
+
+        let mut cache = PageCache::new(TTL);
+        let mut buf = [0u8; 1];
+
+        for page in 0..MAX_PAGES {
+            assert!(read(
+                &mut cache,
+                &mut mem,
+                Address::from(page * PAGE_SIZE),
+                &mut buf
+            ));
+        }
+
+        assert_eq!(cache.pages.len(), MAX_PAGES);
+
+        // No page is stale yet, so the whole cache is dropped to make room.
+        let last = MAX_PAGES * PAGE_SIZE;
+        assert!(read(&mut cache, &mut mem, Address::from(last), &mut buf));
+        assert_eq!(cache.pages.len(), 1);
+        assert!(cache.pages.contains_key(&(last as umem)));
+    }
+
+    #[test]
+    fn stale_pages_are_evicted_first() {
+        let mut mem = MockMemory::new((MAX_PAGES + 1) * PAGE_SIZE, None);
+
+        let mut cache = PageCache::new(TTL);
+        let mut buf = [0u8; 1];
+
+        for page in 0..MAX_PAGES {
+            assert!(read(
+                &mut cache,
+                &mut mem,
+                Address::from(page * PAGE_SIZE),
+                &mut buf
+            ));
+        }
+
+        // Age all but the first page past the time to live.
+        let stale = Instant::now() - TTL * 2;
+        cache
+            .pages
+            .iter_mut()
+            .filter(|(&page, _)| page != 0)
+            .for_each(|(_, (time, _))| *time = stale);
+
+        let last = MAX_PAGES * PAGE_SIZE;
+        assert!(read(&mut cache, &mut mem, Address::from(last), &mut buf));
+
+        let mut pages = cache.pages.keys().copied().collect::<Vec<_>>();
+        pages.sort_unstable();
+        assert_eq!(pages, [0, last as umem]);
+    }
+}
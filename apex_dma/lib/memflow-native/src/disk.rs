@@ -0,0 +1,164 @@
+use memflow::prelude::v1::*;
+
+/// Granularity at which ELF segments are mapped.
+const PAGE_SIZE: usize = 0x1000;
+
+/// Reads the backing file of `module` and lays it out the way the loader maps it, so offsets
+/// into the result are offsets from `module.base`.
+///
+/// PE sections are placed at their virtual addresses, ELF load segments at their virtual
+/// addresses relative to the first one, and the gaps and uninitialized tails are zeroed. The
+/// result is `module.size` bytes long, to be compared against the same range read from the
+/// process. Parts the loader changes at runtime, such as relocated pointers and import tables,
+/// differ even in an unpatched module, see [`module_aslr_slide`](crate::module_aslr_slide).
+pub fn module_disk_image(module: &ModuleInfo) -> Result<Vec<u8>> {
+    let file = std::fs::read(module.path.as_ref())
+        .map_err(|e| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(e))?;
+
+    let mut image = vec![0u8; module.size as usize];
+
+    let parts = match file.get(..4) {
+        Some([b'M', b'Z', ..]) => pe_parts(&file),
+        Some(b"\x7fELF") => elf_parts(&file),
+        _ => None,
+    }
+    .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))?;
+
+    for (offset, range) in parts {
+        let data = file
+            .get(range)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))?;
+
+        // Parts past the end of the module were not mapped along with it.
+        if offset < image.len() {
+            let len = data.len().min(image.len() - offset);
+            image[offset..offset + len].copy_from_slice(&data[..len]);
+        }
+    }
+
+    Ok(image)
+}
+
+fn u16_at(file: &[u8], offset: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(file.get(offset..offset + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_at(file: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(file.get(offset..offset + 4)?.try_into().ok()?) as usize)
+}
+
+fn u64_at(file: &[u8], offset: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(file.get(offset..offset + 8)?.try_into().ok()?) as usize)
+}
+
+/// Lists where the headers and the raw data of each section of a PE file go in the image, as
+/// the offset into the image and the range of the file.
+fn pe_parts(file: &[u8]) -> Option<Vec<(usize, core::ops::Range<usize>)>> {
+    let nt = u32_at(file, 0x3c)?;
+
+    if file.get(nt..nt + 4)? != b"PE\0\0" {
+        return None;
+    }
+
+    let count = u16_at(file, nt + 6)?;
+    let optional_size = u16_at(file, nt + 0x14)?;
+    let headers_size = u32_at(file, nt + 0x18 + 0x3c)?;
+
+    let mut parts = vec![(0, 0..headers_size.min(file.len()))];
+
+    for i in 0..count {
+        let section = nt + 0x18 + optional_size + i * 0x28;
+
+        let virtual_size = u32_at(file, section + 0x08)?;
+        let virtual_address = u32_at(file, section + 0x0c)?;
+        let raw_size = u32_at(file, section + 0x10)?;
+        let raw_offset = u32_at(file, section + 0x14)?;
+
+        // Only the virtual size is mapped, raw data past it is file alignment padding.
+        let len = match virtual_size {
+            0 => raw_size,
+            _ => raw_size.min(virtual_size),
+        };
+
+        parts.push((virtual_address, raw_offset..raw_offset + len));
+    }
+
+    Some(parts)
+}
+
+/// Lists where each load segment of a little-endian ELF file goes in the image, as the offset
+/// into the image and the range of the file.
+fn elf_parts(file: &[u8]) -> Option<Vec<(usize, core::ops::Range<usize>)>> {
+    if *file.get(5)? != 1 {
+        return None;
+    }
+
+    let is_64 = match *file.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+
+    let (phoff, phentsize, phnum) = if is_64 {
+        (
+            u64_at(file, 0x20)?,
+            u16_at(file, 0x36)?,
+            u16_at(file, 0x38)?,
+        )
+    } else {
+        (
+            u32_at(file, 0x1c)?,
+            u16_at(file, 0x2a)?,
+            u16_at(file, 0x2c)?,
+        )
+    };
+
+    let mut segments = vec![];
+
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+
+        // `PT_LOAD`
+        if u32_at(file, header)? != 1 {
+            continue;
+        }
+
+        let (offset, vaddr, filesz, memsz) = if is_64 {
+            (
+                u64_at(file, header + 0x08)?,
+                u64_at(file, header + 0x10)?,
+                u64_at(file, header + 0x20)?,
+                u64_at(file, header + 0x28)?,
+            )
+        } else {
+            (
+                u32_at(file, header + 0x04)?,
+                u32_at(file, header + 0x08)?,
+                u32_at(file, header + 0x10)?,
+                u32_at(file, header + 0x14)?,
+            )
+        };
+
+        segments.push((offset, vaddr, filesz, memsz));
+    }
+
+    // The module starts at the page of the first segment.
+    let base = segments.iter().map(|&(_, vaddr, ..)| vaddr).min()? & !(PAGE_SIZE - 1);
+
+    segments
+        .into_iter()
+        .map(|(offset, vaddr, filesz, memsz)| {
+            // Segments are mapped in whole pages, which pulls in the file contents around them
+            // up to the page boundaries. Only a segment that continues past its file contents
+            // gets the rest of its last page zeroed.
+            let lead = vaddr % PAGE_SIZE;
+            let end = if memsz > filesz {
+                offset + filesz
+            } else {
+                ((offset + filesz + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)).min(file.len())
+            };
+
+            Some((vaddr - lead - base, offset.checked_sub(lead)?..end))
+        })
+        .collect()
+}
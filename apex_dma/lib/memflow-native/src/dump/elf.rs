@@ -0,0 +1,168 @@
+use memflow::prelude::v1::*;
+use memflow::types::size;
+
+use goblin::container::{Container, Ctx, Endian};
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_X86_64, ET_CORE};
+use goblin::elf::note::{NT_FILE, NT_PRPSINFO};
+use goblin::elf::program_header::{PF_W, PF_X, PT_LOAD, PT_NOTE};
+use goblin::elf::{Elf, ProgramHeader};
+
+use std::fs::File;
+
+use super::{int_at, read_vec_at, DumpModule, ParsedDump, Segment};
+
+fn invalid(err: impl std::fmt::Display) -> Error {
+    Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile).log_error(err)
+}
+
+pub(super) fn parse(file: &File) -> Result<ParsedDump> {
+    let header = Elf::parse_header(&read_vec_at(file, 0, 64)?).map_err(invalid)?;
+
+    if header.e_type != ET_CORE {
+        return Err(invalid("not a core dump"));
+    }
+
+    let ctx = Ctx::new(
+        header.container().map_err(invalid)?,
+        header.endianness().map_err(invalid)?,
+    );
+
+    let phdrs = read_vec_at(
+        file,
+        header.e_phoff,
+        header.e_phentsize as usize * header.e_phnum as usize,
+    )?;
+    let phdrs = ProgramHeader::parse(&phdrs, 0, header.e_phnum as usize, ctx).map_err(invalid)?;
+
+    let mut dump = ParsedDump {
+        arch: match header.e_machine {
+            EM_X86_64 => Some(ArchitectureIdent::X86(64, false)),
+            EM_386 => Some(ArchitectureIdent::X86(32, false)),
+            EM_AARCH64 => Some(ArchitectureIdent::AArch64(size::kb(4))),
+            _ => None,
+        },
+        ..Default::default()
+    };
+
+    for phdr in &phdrs {
+        match phdr.p_type {
+            // Only the file backed part of a load segment was captured.
+            PT_LOAD if phdr.p_filesz > 0 => dump.segments.push(Segment {
+                base: Address::from(phdr.p_vaddr),
+                size: phdr.p_filesz as umem,
+                file_offset: phdr.p_offset,
+                page_type: PageType::empty()
+                    .write(phdr.p_flags & PF_W != 0)
+                    .noexec(phdr.p_flags & PF_X == 0),
+            }),
+            PT_NOTE => {
+                let notes = read_vec_at(file, phdr.p_offset, phdr.p_filesz as usize)?;
+                parse_notes(&notes, ctx, &mut dump);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(dump)
+}
+
+fn parse_notes(bytes: &[u8], ctx: Ctx, dump: &mut ParsedDump) {
+    let le = ctx.le == Endian::Little;
+    let align = |v: usize| (v + 3) & !3;
+
+    let mut offset = 0;
+
+    while let (Some(namesz), Some(descsz), Some(ntype)) = (
+        int_at::<4>(bytes, offset, le),
+        int_at::<4>(bytes, offset + 4, le),
+        int_at::<4>(bytes, offset + 8, le),
+    ) {
+        let desc_start = offset + 12 + align(namesz as usize);
+        let desc_end = desc_start + descsz as usize;
+
+        let Some(desc) = bytes.get(desc_start..desc_end) else {
+            break;
+        };
+
+        match ntype as u32 {
+            NT_FILE => dump.modules = parse_file_note(desc, ctx),
+            NT_PRPSINFO => parse_prpsinfo(desc, ctx, dump),
+            _ => {}
+        }
+
+        offset = align(desc_end);
+    }
+}
+
+/// Parses the `NT_FILE` note, merging the mappings of each file into a single module.
+fn parse_file_note(desc: &[u8], ctx: Ctx) -> Vec<DumpModule> {
+    let le = ctx.le == Endian::Little;
+    let word = if ctx.container == Container::Big {
+        8
+    } else {
+        4
+    };
+
+    let read_word = |offset: usize| match word {
+        8 => int_at::<8>(desc, offset, le),
+        _ => int_at::<4>(desc, offset, le),
+    };
+
+    let count = read_word(0).unwrap_or(0) as usize;
+    let entries = 2 * word;
+    let names = entries + count.saturating_mul(3 * word);
+
+    let paths = desc
+        .get(names..)
+        .unwrap_or_default()
+        .split(|&b| b == 0)
+        .map(String::from_utf8_lossy);
+
+    let mut modules: Vec<DumpModule> = vec![];
+
+    for (i, path) in paths.take(count).enumerate() {
+        let entry = entries + i * 3 * word;
+
+        let (Some(start), Some(end)) = (read_word(entry), read_word(entry + word)) else {
+            break;
+        };
+
+        match modules.iter_mut().find(|m| m.path == path) {
+            Some(m) => {
+                let end = Address::from(end).max(m.base + m.size);
+                m.base = m.base.min(Address::from(start));
+                m.size = (end - m.base) as umem;
+            }
+            None => modules.push(DumpModule {
+                base: Address::from(start),
+                size: end.saturating_sub(start) as umem,
+                path: path.into_owned(),
+            }),
+        }
+    }
+
+    modules
+}
+
+/// Parses the `NT_PRPSINFO` note for the process id, name and arguments.
+fn parse_prpsinfo(desc: &[u8], ctx: Ctx, dump: &mut ParsedDump) {
+    let le = ctx.le == Endian::Little;
+
+    // Offsets of pr_pid, pr_fname and pr_psargs in the 64 and 32-bit layouts.
+    let (pid, fname, psargs) = if ctx.container == Container::Big {
+        (24, 40, 56)
+    } else {
+        (12, 28, 44)
+    };
+
+    let cstr = |offset: usize, len: usize| {
+        desc.get(offset..offset + len)
+            .and_then(|s| s.split(|&b| b == 0).next())
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .unwrap_or_default()
+    };
+
+    dump.pid = int_at::<4>(desc, pid, le).unwrap_or(0) as Pid;
+    dump.name = cstr(fname, 16);
+    dump.command_line = cstr(psargs, 80);
+}
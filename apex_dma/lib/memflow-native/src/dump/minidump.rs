@@ -0,0 +1,127 @@
+use memflow::prelude::v1::*;
+use memflow::types::size;
+
+use std::fs::File;
+
+use super::{int_at, read_vec_at, DumpModule, ParsedDump, Segment};
+
+const MODULE_LIST_STREAM: u64 = 4;
+const MEMORY_LIST_STREAM: u64 = 5;
+const SYSTEM_INFO_STREAM: u64 = 7;
+const MEMORY64_LIST_STREAM: u64 = 9;
+const MISC_INFO_STREAM: u64 = 15;
+
+/// Size of `MINIDUMP_MODULE`.
+const MODULE_SIZE: usize = 108;
+
+/// `MINIDUMP_MISC_INFO::Flags1` bit signalling a valid `ProcessId`.
+const MISC1_PROCESS_ID: u64 = 1;
+
+/// Upper bound for module name lengths, guarding against corrupt dumps.
+const MAX_NAME_LEN: u64 = 0x10000;
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u64> {
+    int_at::<4>(bytes, offset, true).ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64> {
+    int_at::<8>(bytes, offset, true).ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))
+}
+
+pub(super) fn parse(file: &File) -> Result<ParsedDump> {
+    let header = read_vec_at(file, 0, 32)?;
+    let stream_count = u32_at(&header, 8)? as usize;
+    let directory_rva = u32_at(&header, 12)?;
+
+    let directory = read_vec_at(file, directory_rva, stream_count * 12)?;
+
+    let mut dump = ParsedDump::default();
+
+    for i in 0..stream_count {
+        let stream_type = u32_at(&directory, i * 12)?;
+        let size = u32_at(&directory, i * 12 + 4)? as usize;
+        let rva = u32_at(&directory, i * 12 + 8)?;
+
+        match stream_type {
+            MODULE_LIST_STREAM => {
+                let stream = read_vec_at(file, rva, size)?;
+
+                for m in 0..u32_at(&stream, 0)? as usize {
+                    let module = 4 + m * MODULE_SIZE;
+
+                    dump.modules.push(DumpModule {
+                        base: Address::from(u64_at(&stream, module)?),
+                        size: u32_at(&stream, module + 8)? as umem,
+                        path: read_string(file, u32_at(&stream, module + 20)?)?,
+                    });
+                }
+            }
+            MEMORY_LIST_STREAM => {
+                let stream = read_vec_at(file, rva, size)?;
+
+                for d in 0..u32_at(&stream, 0)? as usize {
+                    let desc = 4 + d * 16;
+
+                    dump.segments.push(Segment {
+                        base: Address::from(u64_at(&stream, desc)?),
+                        size: u32_at(&stream, desc + 8)? as umem,
+                        file_offset: u32_at(&stream, desc + 12)?,
+                        page_type: PageType::UNKNOWN,
+                    });
+                }
+            }
+            MEMORY64_LIST_STREAM => {
+                let stream = read_vec_at(file, rva, size)?;
+
+                // Full memory dumps store all ranges back to back, starting at the base rva.
+                let mut file_offset = u64_at(&stream, 8)?;
+
+                for d in 0..u64_at(&stream, 0)? as usize {
+                    let desc = 16 + d * 16;
+                    let size = u64_at(&stream, desc + 8)?;
+
+                    dump.segments.push(Segment {
+                        base: Address::from(u64_at(&stream, desc)?),
+                        size: size as umem,
+                        file_offset,
+                        page_type: PageType::UNKNOWN,
+                    });
+
+                    file_offset += size;
+                }
+            }
+            SYSTEM_INFO_STREAM => {
+                let stream = read_vec_at(file, rva, size)?;
+
+                dump.arch = match int_at::<2>(&stream, 0, true) {
+                    Some(9) => Some(ArchitectureIdent::X86(64, false)),
+                    Some(0) => Some(ArchitectureIdent::X86(32, false)),
+                    Some(12) => Some(ArchitectureIdent::AArch64(size::kb(4))),
+                    _ => None,
+                };
+            }
+            MISC_INFO_STREAM => {
+                let stream = read_vec_at(file, rva, size)?;
+
+                if u32_at(&stream, 4)? & MISC1_PROCESS_ID != 0 {
+                    dump.pid = u32_at(&stream, 8)? as Pid;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(dump)
+}
+
+/// Reads a `MINIDUMP_STRING` at the given rva.
+fn read_string(file: &File, rva: u64) -> Result<String> {
+    let len = u32_at(&read_vec_at(file, rva, 4)?, 0)?.min(MAX_NAME_LEN);
+
+    let wide = read_vec_at(file, rva + 4, len as usize)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+
+    Ok(String::from_utf16_lossy(&wide))
+}
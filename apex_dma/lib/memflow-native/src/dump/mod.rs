@@ -0,0 +1,367 @@
+use memflow::cglue;
+use memflow::os::process::*;
+use memflow::prelude::v1::*;
+use memflow::types::gap_remover::GapRemover;
+
+use crate::remote::uint_from_bytes;
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+mod elf;
+mod minidump;
+
+/// A captured memory range of the dump and where its bytes are stored in the file.
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    base: Address,
+    size: umem,
+    file_offset: u64,
+    page_type: PageType,
+}
+
+impl Segment {
+    fn end(&self) -> Address {
+        self.base + self.size
+    }
+}
+
+/// A module mapped into the dumped process.
+#[derive(Clone, Debug)]
+struct DumpModule {
+    base: Address,
+    size: umem,
+    path: String,
+}
+
+/// Everything a dump parser extracts from the file.
+#[derive(Default)]
+struct ParsedDump {
+    pid: Pid,
+    name: String,
+    command_line: String,
+    arch: Option<ArchitectureIdent>,
+    segments: Vec<Segment>,
+    modules: Vec<DumpModule>,
+}
+
+/// A process backed by a memory snapshot on disk.
+///
+/// Supports ELF core dumps and Windows minidumps. The dump is read lazily from the file, so
+/// opening large dumps is cheap. Reads of memory that was not captured in the dump fail, and
+/// writes are rejected since the dump is read-only.
+#[derive(Clone)]
+pub struct DumpProcess {
+    file: Arc<File>,
+    info: ProcessInfo,
+    segments: Vec<Segment>,
+    modules: Vec<ModuleInfo>,
+}
+
+impl DumpProcess {
+    /// Opens a core dump or minidump, detecting the format from the file header.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(e))?;
+
+        let mut magic = [0u8; 4];
+        read_exact_at(&file, &mut magic, 0)?;
+
+        let parsed = match &magic {
+            b"\x7fELF" => elf::parse(&file)?,
+            b"MDMP" => minidump::parse(&file)?,
+            _ => return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)),
+        };
+
+        Ok(Self::from_parsed(file, parsed))
+    }
+
+    fn from_parsed(file: File, parsed: ParsedDump) -> Self {
+        let arch = parsed.arch.unwrap_or(ArchitectureIdent::X86(64, false));
+
+        let mut segments = parsed.segments;
+        segments.sort_by_key(|s| s.base);
+
+        let address = Address::from(parsed.pid as umem);
+
+        let modules = parsed
+            .modules
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| ModuleInfo {
+                address: Address::from(i as umem),
+                parent_process: address,
+                base: m.base,
+                size: m.size,
+                name: file_name(&m.path).into(),
+                path: m.path.into(),
+                arch,
+            })
+            .collect::<Vec<_>>();
+
+        // The primary module is the first one in both formats.
+        let path = modules
+            .first()
+            .map(|m| m.path.clone())
+            .unwrap_or_else(|| parsed.name.clone().into());
+
+        let name = if parsed.name.is_empty() {
+            file_name(&path).into()
+        } else {
+            parsed.name.into()
+        };
+
+        let info = ProcessInfo {
+            address,
+            pid: parsed.pid,
+            state: ProcessState::Unknown,
+            name,
+            path,
+            command_line: parsed.command_line.into(),
+            sys_arch: arch,
+            proc_arch: arch,
+            // dtb is not known/used here
+            dtb1: Address::invalid(),
+            dtb2: Address::invalid(),
+        };
+
+        Self {
+            file: Arc::new(file),
+            info,
+            segments,
+            modules,
+        }
+    }
+
+    /// Index of the first segment that ends after `addr`.
+    fn segment_index(&self, addr: Address) -> usize {
+        self.segments.partition_point(|s| s.end() <= addr)
+    }
+}
+
+cglue_impl_group!(DumpProcess, ProcessInstance, {});
+cglue_impl_group!(DumpProcess, IntoProcessInstance, {});
+
+impl Process for DumpProcess {
+    /// Walks the process' module list and calls the provided callback for each module structure
+    /// address
+    ///
+    /// # Arguments
+    /// * `target_arch` - sets which architecture to retrieve the modules for (if emulated). Choose
+    ///   between `Some(ProcessInfo::sys_arch())`, and `Some(ProcessInfo::proc_arch())`. `None` for all.
+    /// * `callback` - where to pass each matching module to. This is an opaque callback.
+    fn module_address_list_callback(
+        &mut self,
+        target_arch: Option<&ArchitectureIdent>,
+        mut callback: ModuleAddressCallback,
+    ) -> Result<()> {
+        self.modules
+            .iter()
+            .filter(|m| target_arch.is_none() || Some(&m.arch) == target_arch)
+            .take_while(|m| {
+                callback.call(ModuleAddressInfo {
+                    address: m.address,
+                    arch: m.arch,
+                })
+            })
+            .for_each(|_| {});
+
+        Ok(())
+    }
+
+    /// Retrieves a module by its structure address and architecture
+    ///
+    /// # Arguments
+    /// * `address` - address where module's information resides in
+    /// * `architecture` - architecture of the module. Should be either `ProcessInfo::proc_arch`, or `ProcessInfo::sys_arch`.
+    fn module_by_address(
+        &mut self,
+        address: Address,
+        _architecture: ArchitectureIdent,
+    ) -> Result<ModuleInfo> {
+        self.modules
+            .get(address.to_umem() as usize)
+            .cloned()
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
+    }
+
+    fn module_import_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ImportCallback,
+    ) -> Result<()> {
+        memflow::os::util::module_import_list_callback(self, info, callback)
+    }
+
+    fn module_export_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ExportCallback,
+    ) -> Result<()> {
+        memflow::os::util::module_export_list_callback(self, info, callback)
+    }
+
+    fn module_section_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: SectionCallback,
+    ) -> Result<()> {
+        memflow::os::util::module_section_list_callback(self, info, callback)
+    }
+
+    /// Retrieves address of the primary module structure of the process
+    ///
+    /// This will generally be for the initial executable that was run
+    fn primary_module_address(&mut self) -> Result<Address> {
+        self.modules
+            .first()
+            .map(|m| m.address)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    /// Retrieves the process info
+    fn info(&self) -> &ProcessInfo {
+        &self.info
+    }
+
+    /// Retrieves the state of the process
+    fn state(&mut self) -> ProcessState {
+        ProcessState::Unknown
+    }
+
+    /// Changes the dtb this process uses for memory translations.
+    /// This function serves no purpose for dumps.
+    fn set_dtb(&mut self, _dtb1: Address, _dtb2: Address) -> Result<()> {
+        Ok(())
+    }
+
+    fn mapped_mem_range(
+        &mut self,
+        gap_size: imem,
+        start: Address,
+        end: Address,
+        out: MemoryRangeCallback,
+    ) {
+        let mut gap_remover = GapRemover::new(out, gap_size, start, end);
+
+        self.segments[self.segment_index(start)..]
+            .iter()
+            .take_while(|s| s.base < end)
+            .for_each(|s| gap_remover.push_range(CTup3(s.base, s.size, s.page_type)));
+    }
+}
+
+impl MemoryView for DumpProcess {
+    fn read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, buf) in inp {
+            let mut addr = addr;
+            let mut meta_addr = meta_addr;
+            let mut rest = Some(buf);
+
+            // Split the request along segment boundaries, failing the uncaptured parts.
+            while let Some(buf) = rest.take() {
+                let segment = self.segments.get(self.segment_index(addr));
+
+                let (chunk, captured) = match segment {
+                    Some(s) if s.base <= addr => ((s.end() - addr) as umem, Some(s)),
+                    Some(s) => ((s.base - addr) as umem, None),
+                    None => (buf.len() as umem, None),
+                };
+
+                let (head, tail) = buf.split_at(chunk);
+                rest = tail;
+
+                if let Some(mut head) = head {
+                    let len = head.len() as umem;
+
+                    let keep_going = match captured {
+                        Some(s) => {
+                            let offset = s.file_offset + (addr - s.base) as u64;
+                            read_exact_at(&self.file, &mut head, offset)?;
+                            opt_call(out.as_deref_mut(), CTup2(meta_addr, head))
+                        }
+                        None => opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, head)),
+                    };
+
+                    if !keep_going {
+                        return Ok(());
+                    }
+
+                    addr += len;
+                    meta_addr += len;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_raw_iter(&mut self, _data: WriteRawMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::ReadOnly))
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        let arch = self.info.proc_arch.into_obj();
+
+        MemoryViewMetadata {
+            arch_bits: arch.bits(),
+            little_endian: arch.endianess() == Endianess::LittleEndian,
+            max_address: Address::invalid(),
+            readonly: true,
+            real_size: 0,
+        }
+    }
+}
+
+/// Returns the trailing component of a path with either separator.
+fn file_name(path: &str) -> &str {
+    path.rsplit_once(&['\\', '/'][..])
+        .map(|(_, name)| name)
+        .unwrap_or(path)
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buf, offset)
+        .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) | Err(_) => return Err(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile)),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads `len` bytes at `offset` into a new buffer.
+fn read_vec_at(file: &File, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    read_exact_at(file, &mut buf, offset)?;
+    Ok(buf)
+}
+
+/// Reads a little or big endian integer out of a byte buffer, if it is in bounds.
+fn int_at<const N: usize>(bytes: &[u8], offset: usize, le: bool) -> Option<u64> {
+    let raw = bytes.get(offset..offset.checked_add(N)?)?;
+    Some(uint_from_bytes(raw, le))
+}
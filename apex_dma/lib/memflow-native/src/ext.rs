@@ -0,0 +1,541 @@
+use memflow::prelude::v1::*;
+
+use crate::remote::{uint_from_bytes, uint_to_bytes};
+use crate::RemotePtr;
+
+use core::mem::size_of;
+
+/// Granularity at which [`MemoryViewExt::read_zero_filled`] zero fills unreadable memory.
+const PAGE_SIZE: usize = 0x1000;
+
+/// Convenience helpers on top of [`MemoryView`].
+///
+/// These are implemented for every memory view, so they work the same on live processes and
+/// on dumps. The `_into` helpers read into caller provided buffers and never allocate, the
+/// others note their allocations.
+pub trait MemoryViewExt: MemoryView {
+    /// Writes `data` to `addr`, but only if the memory currently holds different bytes.
+    ///
+    /// Returns whether a write was issued. If the current contents can not be read, the write is
+    /// issued unconditionally. Allocates a `data.len()` sized buffer for the comparison.
+    fn write_if_changed(&mut self, addr: Address, data: &[u8]) -> Result<bool> {
+        let mut current = vec![0u8; data.len()];
+
+        if self.read_raw_into(addr, &mut current).is_ok() && current == data {
+            return Ok(false);
+        }
+
+        self.write_raw(addr, data).data()?;
+
+        Ok(true)
+    }
+
+    /// Fills every `(address, length)` range in `ranges` with `byte`.
+    ///
+    /// Overlapping and adjacent ranges are merged first, so each contiguous span is written only
+    /// once. Allocates a single buffer the size of the largest span.
+    fn write_fill(&mut self, ranges: &[(Address, usize)], byte: u8) -> Result<()> {
+        let mut ranges = ranges
+            .iter()
+            .filter(|(_, len)| *len > 0)
+            .map(|&(addr, len)| (addr, addr + len))
+            .collect::<Vec<_>>();
+
+        ranges.sort_unstable_by_key(|&(start, _)| start);
+
+        let mut spans: Vec<(Address, Address)> = vec![];
+
+        for (start, end) in ranges {
+            match spans.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => spans.push((start, end)),
+            }
+        }
+
+        let largest = spans
+            .iter()
+            .map(|&(start, end)| (end - start) as usize)
+            .max()
+            .unwrap_or(0);
+
+        let fill = vec![byte; largest];
+
+        for (start, end) in spans {
+            self.write_raw(start, &fill[..(end - start) as usize])
+                .data()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes each `(address, data)` pair of `writes` in order, as a single batch that stops at
+    /// the first write that fails.
+    ///
+    /// Batched writes of the native processes are always issued in submission order and are
+    /// never merged, but a failed write does not keep the ones after it from being issued. Here
+    /// it does, so a pointer or flag written last is only set once everything it depends on was
+    /// written. The failed write may still have been applied in part. Returns the number of
+    /// writes that completed.
+    fn write_ordered(&mut self, writes: &[(Address, &[u8])]) -> usize {
+        let mut written = 0;
+
+        let ok = &mut |_: WriteData| {
+            written += 1;
+            true
+        };
+        let fail = &mut |_: WriteData| false;
+
+        let ops = writes.iter().map(|&(addr, data)| CTup2(addr, data.into()));
+
+        // Writes that are never reported, as after an error that stops the batch early, did not
+        // complete as far as the caller can tell.
+        let _ = self.write_iter(ops, Some(&mut ok.into()), Some(&mut fail.into()));
+
+        written
+    }
+
+    /// Reads consecutive `step` sized chunks starting at `start` and passes each to `predicate`.
+    ///
+    /// Reading stops once `predicate` returns false, a chunk can not be read in full, or
+    /// `max_steps` chunks were visited. Returns the number of chunks passed to `predicate`.
+    /// Allocates a single `step` sized buffer per call.
+    fn read_while(
+        &mut self,
+        start: Address,
+        step: usize,
+        max_steps: usize,
+        mut predicate: impl FnMut(Address, &[u8]) -> bool,
+    ) -> usize {
+        let mut chunk = vec![0u8; step];
+        let mut addr = start;
+
+        for visited in 0..max_steps {
+            if self.read_raw_into(addr, &mut chunk).is_err() {
+                return visited;
+            }
+
+            if !predicate(addr, &chunk) {
+                return visited + 1;
+            }
+
+            addr += step;
+        }
+
+        max_steps
+    }
+
+    /// Reads a NUL terminated string at `addr` into `buf`, returning the bytes before the NUL.
+    ///
+    /// The string is cut off at `buf.len()` bytes, and unreadable memory ends it early.
+    fn read_cstr_into<'a>(&mut self, addr: Address, buf: &'a mut [u8]) -> Result<&'a [u8]> {
+        self.read_raw_into(addr, buf).data_part()?;
+
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+
+        Ok(&buf[..len])
+    }
+
+    /// Reads the `bit_count` bits starting `bit_offset` bits past `addr`.
+    ///
+    /// Bits are numbered from the least significant bit of each byte, with the bytes in
+    /// little-endian order, which is how compilers for x86 and ARM lay out bitfields. Only the
+    /// bytes covering the range are read. Fails with `InvalidArgument` if `bit_count` exceeds 64.
+    fn read_bits(&mut self, addr: Address, bit_offset: usize, bit_count: usize) -> Result<u64> {
+        if bit_count > 64 {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument));
+        }
+
+        if bit_count == 0 {
+            return Ok(0);
+        }
+
+        let first = bit_offset / 8;
+        let last = (bit_offset + bit_count - 1) / 8;
+        let shift = bit_offset % 8;
+
+        // A 64-bit range that does not start on a byte boundary spans nine bytes.
+        let mut bytes = [0u8; 16];
+        self.read_raw_into(addr + first, &mut bytes[..=last - first])?;
+
+        let value = u128::from_le_bytes(bytes) >> shift;
+
+        Ok((value & (u128::MAX >> (128 - bit_count))) as u64)
+    }
+
+    /// Reads the contents of the Windows `UNICODE_STRING` at `addr`.
+    ///
+    /// The layout follows the pointer width from the view's metadata, the buffer pointer comes
+    /// after the two lengths, aligned to the pointer size. Empty strings are returned without
+    /// reading their buffer, which is often null. Allocates the returned string and a buffer for
+    /// its UTF-16 contents.
+    fn read_unicode_string(&mut self, addr: Address) -> Result<String> {
+        let little_endian = self.metadata().little_endian;
+
+        let mut len = [0u8; 2];
+        self.read_raw_into(addr, &mut len)?;
+        let len = uint_from_bytes(&len, little_endian) as usize;
+
+        if len == 0 {
+            return Ok(String::new());
+        }
+
+        let ptr_size = self.metadata().arch_bits as usize / 8;
+        let buffer = RemotePtr::<u16>::read_at(self, addr + ptr_size)?;
+
+        let mut raw = vec![0u8; len & !1];
+        self.read_raw_into(buffer.address, &mut raw)?;
+
+        let wide = raw
+            .chunks_exact(2)
+            .map(|c| uint_from_bytes(c, little_endian) as u16)
+            .collect::<Vec<_>>();
+
+        Ok(String::from_utf16_lossy(&wide))
+    }
+
+    /// Reads `out.len()` pointers at `addr` into `out`, widening 32-bit pointers to 64 bits.
+    ///
+    /// The pointer width and byte order are taken from the view's metadata. Unreadable pointers
+    /// are read as null.
+    fn read_ptr_array_into(&mut self, addr: Address, out: &mut [u64]) -> Result<()> {
+        let metadata = self.metadata();
+        let width = if metadata.arch_bits == 32 { 4 } else { 8 };
+
+        let len = out.len();
+        let bytes = out.as_bytes_mut();
+
+        if width == 8 && metadata.little_endian == cfg!(target_endian = "little") {
+            return self.read_raw_into(addr, bytes).data_part();
+        }
+
+        self.read_raw_into(addr, &mut bytes[..len * width])
+            .data_part()?;
+
+        // Convert in place from the back, so every pointer is read before it gets overwritten.
+        for i in (0..len).rev() {
+            let ptr = uint_from_bytes(&bytes[i * width..(i + 1) * width], metadata.little_endian);
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&ptr.to_ne_bytes());
+        }
+
+        Ok(())
+    }
+
+    /// Reads the `size` byte unsigned integer at `addr`, in the byte order from the view's
+    /// metadata.
+    ///
+    /// Typed reads such as [`MemoryView::read`] copy values as they are stored, which only
+    /// matches the host if the target has the same byte order. Fails with `InvalidArgument` if
+    /// `size` exceeds 8.
+    fn read_uint(&mut self, addr: Address, size: usize) -> Result<u64> {
+        if size > 8 {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument));
+        }
+
+        let mut raw = [0u8; 8];
+        self.read_raw_into(addr, &mut raw[..size])?;
+
+        Ok(uint_from_bytes(&raw[..size], self.metadata().little_endian))
+    }
+
+    /// Writes the low `size` bytes of `value` to `addr`, in the byte order from the view's
+    /// metadata.
+    ///
+    /// The counterpart of [`Self::read_uint`]. Fails with `InvalidArgument` if `size` exceeds 8.
+    fn write_uint(&mut self, addr: Address, size: usize, value: u64) -> Result<()> {
+        if size > 8 {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument));
+        }
+
+        let mut raw = [0u8; 8];
+        uint_to_bytes(value, &mut raw[..size], self.metadata().little_endian);
+
+        self.write_raw(addr, &raw[..size]).data()
+    }
+
+    /// Reads a `T` at each of `addrs` in a single batch.
+    ///
+    /// Each read succeeds or fails on its own, so unreadable addresses such as null slots of an
+    /// entity list come back as `None` without affecting the others. Allocates the returned
+    /// values and one byte count per address.
+    fn read_each<T: Pod>(&mut self, addrs: &[Address]) -> Vec<Option<T>> {
+        let mut values = (0..addrs.len()).map(|_| T::zeroed()).collect::<Vec<_>>();
+        let mut read = vec![0usize; addrs.len()];
+
+        let base = values.as_ptr() as usize;
+
+        // Reads are told apart by their buffer, as addresses may repeat. Backends may split a
+        // read, such as the page cache at page boundaries, so a value only counts as read once
+        // all of its bytes were reported.
+        let ok = &mut |CTup2(_, buf): ReadData| {
+            read[(buf.as_ptr() as usize - base) / size_of::<T>().max(1)] += buf.len();
+            true
+        };
+
+        let ops = addrs
+            .iter()
+            .zip(values.iter_mut())
+            .map(|(&addr, value)| CTup2(addr, value.as_bytes_mut().into()));
+
+        // Only reads reported as successful are used, so errors that stop the batch early need
+        // no handling of their own.
+        let _ = self.read_iter(ops, Some(&mut ok.into()), None);
+
+        values
+            .into_iter()
+            .zip(read)
+            .map(|(value, read)| (read == size_of::<T>()).then_some(value))
+            .collect()
+    }
+
+    /// Reads the `count` elements of the array of `T` at `base` and passes each to `callback`
+    /// along with its index.
+    ///
+    /// The array is read a page worth of elements at a time into a buffer that is reused, so
+    /// memory use stays the same regardless of the array size. Iteration stops once `callback`
+    /// returns false, and fails once a chunk can not be read in full, after the elements before
+    /// it were passed on. Returns the number of elements passed to `callback`.
+    fn for_each_element<T: Pod>(
+        &mut self,
+        base: Address,
+        count: usize,
+        mut callback: impl FnMut(usize, &T) -> bool,
+    ) -> Result<usize> {
+        let chunk_len = (PAGE_SIZE / size_of::<T>().max(1)).max(1).min(count);
+        let mut chunk = (0..chunk_len).map(|_| T::zeroed()).collect::<Vec<_>>();
+
+        let mut index = 0;
+
+        while index < count {
+            let len = chunk_len.min(count - index);
+            let elements = &mut chunk[..len];
+
+            self.read_raw_into(base + index * size_of::<T>(), elements.as_bytes_mut())?;
+
+            for element in elements.iter() {
+                index += 1;
+
+                if !callback(index - 1, element) {
+                    return Ok(index);
+                }
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Reads the `len` bytes at `addr` into one contiguous buffer, with every byte that can not
+    /// be read set to zero.
+    ///
+    /// The range is read page by page in a single batch, so an unmapped or protected page only
+    /// zeroes itself instead of failing the whole read, and offsets into the buffer are always
+    /// offsets from `addr`. Holes can not be told apart from memory that holds zeroes, use
+    /// [`read_range_detailed`](crate::read_range_detailed) where they matter. Allocates the
+    /// returned buffer.
+    fn read_zero_filled(&mut self, addr: Address, len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; len];
+        let mut failed = vec![];
+
+        // The first chunk ends at a page boundary, so a failing page never takes others with it.
+        let head = (PAGE_SIZE - (addr.to_umem() as usize % PAGE_SIZE)).min(len);
+        let (first, rest) = buf.split_at_mut(head);
+
+        let ops = core::iter::once((addr, first))
+            .chain(
+                rest.chunks_mut(PAGE_SIZE)
+                    .enumerate()
+                    .map(|(i, chunk)| (addr + head + i * PAGE_SIZE, chunk)),
+            )
+            .map(|(addr, chunk)| CTup2(addr, chunk.into()));
+
+        let fail = &mut |CTup2(fail_addr, data): ReadData| {
+            let start = (fail_addr - addr) as usize;
+            failed.push(start..start + data.len());
+            true
+        };
+
+        // Reads that are never reported, as after an error that stops the batch early, are left
+        // zeroed as well.
+        let _ = self.read_iter(ops, None, Some(&mut fail.into()));
+
+        // Failed reads may still have transferred a part of their chunk.
+        for range in failed {
+            buf[range].fill(0);
+        }
+
+        buf
+    }
+
+    /// Reads the `index`-th entry of the vtable of the object at `object`.
+    ///
+    /// The vtable pointer is the first member of the object. Both it and the entries are read
+    /// with the pointer width from the view's metadata.
+    fn read_vfunc(&mut self, object: Address, index: usize) -> Result<Address> {
+        let vtable = RemotePtr::<()>::read_at(self, object)?;
+        Ok(RemotePtr::<()>::read_index(self, vtable.address, index)?.address)
+    }
+
+    /// Resolves the target of an x86 instruction with a `rel32` operand, such as `E8` calls or
+    /// `E9` jumps.
+    ///
+    /// The signed displacement is read at `addr + disp_offset` and is relative to the end of the
+    /// instruction, so the target is `addr + instr_len + disp`.
+    fn resolve_rel(
+        &mut self,
+        addr: Address,
+        instr_len: usize,
+        disp_offset: usize,
+    ) -> Result<Address> {
+        let mut disp = [0u8; 4];
+        self.read_raw_into(addr + disp_offset, &mut disp)?;
+
+        let disp = i32::from_le_bytes(disp) as i64;
+
+        Ok(Address::from(
+            (addr + instr_len).to_umem().wrapping_add(disp as umem),
+        ))
+    }
+
+    /// Resolves the address referenced by an x86-64 instruction with a RIP-relative memory
+    /// operand, such as `48 8B 05` (`mov rax, [rip+rel32]`).
+    ///
+    /// The displacement works like a `rel32` operand, see [`Self::resolve_rel`]. This returns
+    /// the address of the referenced data, usually a global variable, not its contents. Use
+    /// [`Self::read_rip_relative_ptr`] for globals that hold a pointer.
+    fn resolve_rip_relative(
+        &mut self,
+        instr_addr: Address,
+        instr_len: usize,
+        disp_offset: usize,
+    ) -> Result<Address> {
+        self.resolve_rel(instr_addr, instr_len, disp_offset)
+    }
+
+    /// Reads the pointer stored at the address referenced by a RIP-relative instruction, see
+    /// [`Self::resolve_rip_relative`].
+    ///
+    /// The pointer is read with the pointer width from the view's metadata.
+    fn read_rip_relative_ptr(
+        &mut self,
+        instr_addr: Address,
+        instr_len: usize,
+        disp_offset: usize,
+    ) -> Result<Address> {
+        let global = self.resolve_rip_relative(instr_addr, instr_len, disp_offset)?;
+        Ok(RemotePtr::<()>::read_at(self, global)?.address)
+    }
+}
+
+impl<T: MemoryView> MemoryViewExt for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::mock::MockMemory;
+
+    #[test]
+    fn read_each_reassembles_split_reads() {
+        let mut mem = MockMemory::new(0x3000, Some(Address::from(0x2000)));
+        mem.data[0xffc..0x1004].copy_from_slice(&0x1122_3344_5566_7788u64.to_le_bytes());
+        mem.data[0x100..0x108].copy_from_slice(&42u64.to_le_bytes());
+
+        let values = mem.read_each::<u64>(&[
+            Address::from(0xffc),
+            Address::from(0x100),
+            Address::from(0x1ffc),
+            Address::from(0x100),
+        ]);
+
+        // The first value straddles a page and is read in two parts, the third one touches the
+        // failing address in its second part.
+        assert_eq!(
+            values,
+            [Some(0x1122_3344_5566_7788), Some(42), None, Some(42)]
+        );
+    }
+
+    #[test]
+    fn write_ordered_stops_at_failed_write() {
+        let mut mem = MockMemory::new(0x3000, Some(Address::from(0x2002)));
+
+        let writes: [(Address, &[u8]); 4] = [
+            (Address::from(0x100), &[1; 4]),
+            (Address::from(0x1000), &[2; 4]),
+            (Address::from(0x2000), &[3; 4]),
+            (Address::from(0x200), &[4; 4]),
+        ];
+
+        assert_eq!(mem.write_ordered(&writes), 2);
+
+        // The writes are submitted in order, and none are submitted after the failed one.
+        assert_eq!(
+            mem.writes,
+            [0x100, 0x1000, 0x2000].map(Address::from).to_vec()
+        );
+
+        assert_eq!(mem.data[0x100..0x104], [1; 4]);
+        assert_eq!(mem.data[0x1000..0x1004], [2; 4]);
+        assert_eq!(mem.data[0x2000..0x2004], [0; 4]);
+        assert_eq!(mem.data[0x200..0x204], [0; 4]);
+    }
+
+    #[test]
+    fn write_ordered_counts_all_writes() {
+        let mut mem = MockMemory::new(0x3000, None);
+
+        let writes: [(Address, &[u8]); 3] = [
+            (Address::from(0x2000), &[1; 4]),
+            (Address::from(0x100), &[2; 4]),
+            (Address::from(0x2000), &[3; 4]),
+        ];
+
+        assert_eq!(mem.write_ordered(&writes), 3);
+        assert_eq!(
+            mem.writes,
+            [0x2000, 0x100, 0x2000].map(Address::from).to_vec()
+        );
+
+        // The later write to the same address wins.
+        assert_eq!(mem.data[0x2000..0x2004], [3; 4]);
+    }
+
+    #[test]
+    fn resolve_rel_handles_negative_displacements() {
+        let mut mem = MockMemory::new(0x3000, None);
+
+        // `call $`, a call to its own start.
+        mem.data[0x1000..0x1005].copy_from_slice(&[0xe8, 0xfb, 0xff, 0xff, 0xff]);
+
+        assert_eq!(
+            mem.resolve_rel(Address::from(0x1000), 5, 1).unwrap(),
+            Address::from(0x1000)
+        );
+    }
+
+    #[test]
+    fn resolve_rel_handles_positive_displacements() {
+        let mut mem = MockMemory::new(0x3000, None);
+
+        // `jmp` 0x20 bytes past the end of the instruction.
+        mem.data[0x1000..0x1005].copy_from_slice(&[0xe9, 0x20, 0x00, 0x00, 0x00]);
+
+        // `mov rax, [rip+0x100]`, with the displacement at offset 3.
+        mem.data[0x2000..0x2007].copy_from_slice(&[0x48, 0x8b, 0x05, 0x00, 0x01, 0x00, 0x00]);
+        mem.data[0x2107..0x210f].copy_from_slice(&0x1234u64.to_le_bytes());
+
+        assert_eq!(
+            mem.resolve_rel(Address::from(0x1000), 5, 1).unwrap(),
+            Address::from(0x1025)
+        );
+        assert_eq!(
+            mem.resolve_rip_relative(Address::from(0x2000), 7, 3)
+                .unwrap(),
+            Address::from(0x2107)
+        );
+        assert_eq!(
+            mem.read_rip_relative_ptr(Address::from(0x2000), 7, 3)
+                .unwrap(),
+            Address::from(0x1234)
+        );
+    }
+}
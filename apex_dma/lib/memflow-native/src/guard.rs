@@ -0,0 +1,306 @@
+use memflow::prelude::v1::*;
+
+#[cfg(not(target_os = "macos"))]
+use std::cell::RefCell;
+
+/// Default for the `max_read_size` argument (256 MiB).
+#[cfg(not(target_os = "macos"))]
+pub(crate) const DEFAULT_MAX_READ_SIZE: usize = 256 << 20;
+
+/// Default for the `min_address` argument, the null page.
+#[cfg(not(target_os = "macos"))]
+pub(crate) const DEFAULT_MIN_ADDRESS: usize = 0x1000;
+
+/// Passes the reads on to `read`, failing any single read larger than `max_size`.
+///
+/// Such reads are usually a corrupt length, so they are never passed to the system. They are
+/// reported to `out_fail` like any other failed read, and the rest of the batch is carried out.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn checked_read(
+    MemOps {
+        inp,
+        mut out,
+        out_fail,
+    }: ReadRawMemOps,
+    max_size: usize,
+    read: impl FnOnce(ReadRawMemOps) -> Result<()>,
+) -> Result<()> {
+    let out_fail = RefCell::new(out_fail);
+
+    let iter = inp.filter_map(|CTup3(addr, meta_addr, buf)| {
+        if check_size(buf.len(), max_size).is_ok() {
+            return Some(CTup3(addr, meta_addr, buf));
+        }
+
+        opt_call(out_fail.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf));
+        None
+    });
+
+    let ok = &mut |data| opt_call(out.as_deref_mut(), data);
+    let fail = &mut |data| opt_call(out_fail.borrow_mut().as_deref_mut(), data);
+
+    MemOps::with_raw(iter, Some(&mut ok.into()), Some(&mut fail.into()), read)
+}
+
+/// Passes the reads on to `read`, failing the ones that start below `min_address`.
+///
+/// Such reads are usually a dereferenced null pointer plus an offset, so they are never passed
+/// to the system. They are reported to `out_fail` like any other read that failed, so the rest
+/// of the batch is carried out and `read_raw_into` reports the usual partial read. A
+/// `min_address` of zero disables the check.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn low_checked_read(
+    ops: ReadRawMemOps,
+    min_address: usize,
+    read: impl FnOnce(ReadRawMemOps) -> Result<()>,
+) -> Result<()> {
+    if min_address == 0 {
+        return read(ops);
+    }
+
+    let MemOps {
+        inp,
+        mut out,
+        out_fail,
+    } = ops;
+    let out_fail = RefCell::new(out_fail);
+
+    let iter = inp.filter_map(|CTup3(addr, meta_addr, buf)| {
+        if addr.to_umem() >= min_address as umem {
+            return Some(CTup3(addr, meta_addr, buf));
+        }
+
+        opt_call(out_fail.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf));
+        None
+    });
+
+    let ok = &mut |data| opt_call(out.as_deref_mut(), data);
+    let fail = &mut |data| opt_call(out_fail.borrow_mut().as_deref_mut(), data);
+
+    MemOps::with_raw(iter, Some(&mut ok.into()), Some(&mut fail.into()), read)
+}
+
+/// Fails with `OutOfBounds` if `addr` lies below `min_address`.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn check_address(addr: Address, min_address: usize) -> Result<()> {
+    if addr.to_umem() < min_address as umem {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds));
+    }
+
+    Ok(())
+}
+
+/// Fails with `OutOfBounds` if a read of `len` bytes exceeds `max_size`.
+pub(crate) fn check_size(len: usize, max_size: usize) -> Result<()> {
+    if len > max_size {
+        return Err(
+            Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds).log_error(format!(
+                "read of {len:#x} bytes exceeds the maximum read size of {max_size:#x}"
+            )),
+        );
+    }
+
+    Ok(())
+}
+
+/// Computes the address `offset` bytes below the stack base `base`, failing with `OutOfBounds`
+/// if a read of `len` bytes there would reach past the base.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn stack_addr(base: Address, offset: umem, len: usize) -> Result<Address> {
+    if offset < len as umem || offset > base.to_umem() {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds));
+    }
+
+    Ok(base - offset)
+}
+
+/// Fails with `InvalidArchitecture` if the image at `base` is `image_bits` wide while the process
+/// is read as `proc_arch`.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn check_arch_bits(
+    proc_arch: ArchitectureIdent,
+    image_bits: u8,
+    base: Address,
+) -> Result<()> {
+    let proc_bits = proc_arch.into_obj().bits();
+
+    if proc_bits != image_bits {
+        return Err(
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArchitecture).log_error(format!(
+                "process is read as {proc_bits}-bit, but the primary module at {base:x} is \
+                {image_bits}-bit"
+            )),
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks whether `len` bytes at `addr` lie within the address space of the host.
+///
+/// Only 32-bit builds can fail this, as they can not pass addresses above 4 GiB to the system.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn host_addressable(addr: Address, len: usize) -> bool {
+    usize::try_from(addr.to_umem())
+        .ok()
+        .and_then(|addr| addr.checked_add(len))
+        .is_some()
+}
+
+/// Passes the reads on to `read`, failing the ones the host can not address.
+///
+/// Truncating such addresses to the host pointer width would read the wrong memory.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn host_checked_read(
+    ops: ReadRawMemOps,
+    read: impl FnOnce(ReadRawMemOps) -> Result<()>,
+) -> Result<()> {
+    if cfg!(target_pointer_width = "64") {
+        return read(ops);
+    }
+
+    let MemOps {
+        inp,
+        mut out,
+        out_fail,
+    } = ops;
+    let out_fail = RefCell::new(out_fail);
+
+    let iter = inp.filter_map(|CTup3(addr, meta_addr, buf)| {
+        if host_addressable(addr, buf.len()) {
+            return Some(CTup3(addr, meta_addr, buf));
+        }
+
+        opt_call(out_fail.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf));
+        None
+    });
+
+    // Both callbacks are wrapped, as they have to share a lifetime.
+    let ok = &mut |data| opt_call(out.as_deref_mut(), data);
+    let fail = &mut |data| opt_call(out_fail.borrow_mut().as_deref_mut(), data);
+
+    MemOps::with_raw(iter, Some(&mut ok.into()), Some(&mut fail.into()), read)
+}
+
+/// Passes the writes on to `write`, failing the ones the host can not address.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn host_checked_write(
+    ops: WriteRawMemOps,
+    write: impl FnOnce(WriteRawMemOps) -> Result<()>,
+) -> Result<()> {
+    if cfg!(target_pointer_width = "64") {
+        return write(ops);
+    }
+
+    let MemOps {
+        inp,
+        mut out,
+        out_fail,
+    } = ops;
+    let out_fail = RefCell::new(out_fail);
+
+    let iter = inp.filter_map(|CTup3(addr, meta_addr, buf)| {
+        if host_addressable(addr, buf.len()) {
+            return Some(CTup3(addr, meta_addr, buf));
+        }
+
+        opt_call(out_fail.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf));
+        None
+    });
+
+    let ok = &mut |data| opt_call(out.as_deref_mut(), data);
+    let fail = &mut |data| opt_call(out_fail.borrow_mut().as_deref_mut(), data);
+
+    MemOps::with_raw(iter, Some(&mut ok.into()), Some(&mut fail.into()), write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_reads_fail_without_failing_the_batch() {
+        let mut bufs = [[0u8; 4]; 3];
+        let mut read = vec![];
+        let mut failed = vec![];
+
+        let ok = &mut |CTup2(addr, _): ReadData| {
+            read.push(addr);
+            true
+        };
+        let fail = &mut |CTup2(addr, _): ReadData| {
+            failed.push(addr);
+            true
+        };
+
+        let ops = [0x2000, 0x10, 0x3000]
+            .into_iter()
+            .zip(bufs.iter_mut())
+            .map(|(addr, buf)| {
+                CTup3(
+                    Address::from(addr),
+                    Address::from(addr),
+                    buf.as_mut_slice().into(),
+                )
+            });
+
+        // Every read that gets through succeeds.
+        let result = MemOps::with_raw(ops, Some(&mut ok.into()), Some(&mut fail.into()), |ops| {
+            low_checked_read(
+                ops,
+                DEFAULT_MIN_ADDRESS,
+                |MemOps { inp, mut out, .. }| {
+                    for CTup3(_, meta_addr, buf) in inp {
+                        opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+                    }
+                    Ok(())
+                },
+            )
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(read, [0x2000, 0x3000].map(Address::from).to_vec());
+        assert_eq!(failed, [Address::from(0x10)].to_vec());
+    }
+
+    #[test]
+    fn oversized_reads_fail_without_failing_the_batch() {
+        let mut bufs = [vec![0u8; 4], vec![0u8; 16], vec![0u8; 4]];
+        let mut read = vec![];
+        let mut failed = vec![];
+
+        let ok = &mut |CTup2(addr, _): ReadData| {
+            read.push(addr);
+            true
+        };
+        let fail = &mut |CTup2(addr, _): ReadData| {
+            failed.push(addr);
+            true
+        };
+
+        let ops = [0x1000, 0x2000, 0x3000]
+            .into_iter()
+            .zip(bufs.iter_mut())
+            .map(|(addr, buf)| {
+                CTup3(
+                    Address::from(addr),
+                    Address::from(addr),
+                    buf.as_mut_slice().into(),
+                )
+            });
+
+        // Every read that gets through succeeds.
+        let result = MemOps::with_raw(ops, Some(&mut ok.into()), Some(&mut fail.into()), |ops| {
+            checked_read(ops, 8, |MemOps { inp, mut out, .. }| {
+                for CTup3(_, meta_addr, buf) in inp {
+                    opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+                }
+                Ok(())
+            })
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(read, [0x1000, 0x3000].map(Address::from).to_vec());
+        assert_eq!(failed, [Address::from(0x2000)].to_vec());
+    }
+}
@@ -0,0 +1,60 @@
+use memflow::prelude::v1::*;
+
+use std::sync::Arc;
+
+/// A callback run with the info of a process that was attached to or detached from.
+pub type ProcessHook = Arc<dyn Fn(&ProcessInfo) + Send + Sync>;
+
+/// The hooks registered on an OS instance.
+#[cfg(not(target_os = "macos"))]
+#[derive(Clone, Default)]
+pub(crate) struct AttachHooks {
+    pub on_attach: Option<ProcessHook>,
+    pub on_detach: Option<ProcessHook>,
+}
+
+#[cfg(not(target_os = "macos"))]
+impl AttachHooks {
+    /// Runs the attach hook for `info`, returning the guard that runs the detach hook.
+    ///
+    /// Returns `None` if no hook is registered, so processes opened without hooks carry nothing.
+    pub fn attach(&self, info: &ProcessInfo) -> Option<Arc<Attached>> {
+        if self.on_attach.is_none() && self.on_detach.is_none() {
+            return None;
+        }
+
+        if let Some(hook) = &self.on_attach {
+            hook(info);
+        }
+
+        Some(Arc::new(Attached {
+            info: info.clone(),
+            hooks: self.clone(),
+        }))
+    }
+}
+
+/// Runs the detach hook once dropped.
+///
+/// Clones of a process share it, so the hook runs once the last of them is dropped.
+#[cfg(not(target_os = "macos"))]
+pub(crate) struct Attached {
+    info: ProcessInfo,
+    hooks: AttachHooks,
+}
+
+#[cfg(not(target_os = "macos"))]
+impl Attached {
+    pub fn hooks(&self) -> &AttachHooks {
+        &self.hooks
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl Drop for Attached {
+    fn drop(&mut self) {
+        if let Some(hook) = &self.hooks.on_detach {
+            hook(&self.info);
+        }
+    }
+}
@@ -1,6 +1,10 @@
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
+pub use linux::iter::{ProcessEntry, ProcessIter};
+#[cfg(target_os = "linux")]
+pub use linux::mem::ReadMethod;
+#[cfg(target_os = "linux")]
 pub use linux::LinuxOs as NativeOs;
 
 #[cfg(target_os = "macos")]
@@ -11,10 +15,79 @@ pub use macos::MacOs as NativeOs;
 #[cfg(target_os = "windows")]
 mod windows;
 #[cfg(target_os = "windows")]
+pub use self::windows::iter::{ProcessEntry, ProcessIter};
+#[cfg(target_os = "windows")]
 pub use self::windows::WindowsOs as NativeOs;
 #[cfg(target_os = "windows")]
 use crate::keyboard::OsKeyboardVtbl;
 
+#[cfg(not(target_os = "macos"))]
+mod align;
+
+#[cfg(not(target_os = "macos"))]
+mod args;
+
+#[cfg(not(target_os = "macos"))]
+mod cache;
+
+mod disk;
+pub use disk::module_disk_image;
+
+mod dump;
+pub use dump::DumpProcess;
+
+mod ext;
+pub use ext::MemoryViewExt;
+
+mod guard;
+
+mod hooks;
+pub use hooks::ProcessHook;
+
+#[cfg(not(target_os = "macos"))]
+mod lru;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(not(target_os = "macos"))]
+mod os_error;
+
+mod pe;
+pub use pe::{
+    module_aslr_slide, module_code_hash, module_sections, read_module_offsets, rva_to_va, va_to_rva,
+};
+
+mod range;
+pub use range::{read_range_detailed, RangeRead};
+
+mod read_set;
+pub use read_set::ReadSet;
+
+mod remote;
+pub use remote::RemotePtr;
+
+mod skip;
+pub use skip::SkipReason;
+
+mod snapshot;
+pub use snapshot::RegionSnapshot;
+
+mod stats;
+pub use stats::{Stats, StatsSnapshot};
+
+#[cfg(not(target_os = "macos"))]
+mod tree;
+
+mod types;
+pub use types::{
+    HandleInfo, LenKind, MappedFile, MappedRegion, MemInfo, ModuleSection, RegionPermissions,
+    ResourceCounts, SectionFlags, TebInfo, ThreadSnapshot,
+};
+
+mod watch;
+pub use watch::{watch, watch_modules, Watch};
+
 use memflow::cglue;
 use memflow::prelude::v1::*;
 
@@ -23,8 +96,81 @@ cglue_impl_group!(NativeOs, OsInstance, { OsKeyboard });
 #[cfg(not(target_os = "windows"))]
 cglue_impl_group!(NativeOs, OsInstance, {});
 
-#[cfg_attr(feature = "plugins", os(name = "native", return_wrapped = true))]
+#[cfg_attr(
+    feature = "plugins",
+    os(name = "native", help_fn = "help", return_wrapped = true)
+)]
 pub fn create_os(args: &OsArgs, lib: LibArc) -> Result<OsInstanceArcBox<'static>> {
     let os = NativeOs::new(args)?;
     Ok(memflow::plugins::os::create_instance(os, lib, args))
 }
+
+/// Lists the extra arguments the plugin accepts on this platform.
+#[cfg(feature = "plugins")]
+pub fn help() -> String {
+    let args: &[(&str, &str)] = if cfg!(target_os = "windows") {
+        &[
+            (
+                "elevate_token",
+                "enable the `privileges` on startup (default: on)",
+            ),
+            (
+                "max_read_size",
+                "reject single reads larger than this, e.g. `1g` (default: 256m)",
+            ),
+            (
+                "min_address",
+                "reject reads below this address, `0` to allow all (default: 0x1000)",
+            ),
+            (
+                "module_source",
+                "`psapi` (default) or `peb` to walk the loader list in memory",
+            ),
+            (
+                "open_timeout",
+                "give up opening a process after this many milliseconds, `0` to wait (default: 0)",
+            ),
+            (
+                "privileges",
+                "quoted, comma separated privileges to enable (default: SeDebugPrivilege)",
+            ),
+            (
+                "skip_system",
+                "leave System and Idle out of process lists (default: off)",
+            ),
+        ]
+    } else if cfg!(target_os = "linux") {
+        &[
+            (
+                "read_method",
+                "`vm_readv` (default) or `procmem` for /proc/pid/mem reads, falls back to the other",
+            ),
+            (
+                "max_read_size",
+                "reject single reads larger than this, e.g. `1g` (default: 256m)",
+            ),
+            (
+                "min_address",
+                "reject reads below this address, `0` to allow all (default: 0x1000)",
+            ),
+            (
+                "skip_system",
+                "leave kernel threads out of process lists (default: off)",
+            ),
+        ]
+    } else {
+        &[]
+    };
+
+    let mut help = "The native OS layer accesses processes of the running system.\n".to_string();
+
+    if !args.is_empty() {
+        help.push_str("\nArguments:\n");
+
+        for (name, desc) in args {
+            help.push_str(&format!("  {name:<16}{desc}\n"));
+        }
+    }
+
+    help
+}
@@ -0,0 +1,128 @@
+use memflow::os::process::*;
+use memflow::prelude::v1::*;
+
+use procfs::process::Process;
+
+use super::{exe_arch, proc_pids, process_info, skip_reason};
+use crate::skip::SkipLog;
+
+/// A process from a snapshot of the process list.
+///
+/// The snapshot only carries ids and the process name, read from `/proc/pid/comm`. Everything
+/// else is read from procfs when it is asked for.
+pub struct ProcessEntry {
+    pub pid: Pid,
+    pub name: String,
+}
+
+impl ProcessEntry {
+    /// Retrieves the id of the parent process.
+    pub fn parent_pid(&mut self) -> Result<Pid> {
+        Ok(self
+            .proc()?
+            .stat()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))?
+            .ppid as Pid)
+    }
+
+    /// Retrieves the command line the process was started with.
+    pub fn command_line(&mut self) -> Result<String> {
+        Ok(self
+            .proc()?
+            .cmdline()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))?
+            .join(" "))
+    }
+
+    /// Retrieves the command line split into individual arguments.
+    ///
+    /// `/proc/pid/cmdline` is already NUL separated, so no quoting rules apply.
+    pub fn argv(&mut self) -> Result<Vec<String>> {
+        self.proc()?
+            .cmdline()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))
+    }
+
+    /// Retrieves the architecture the process runs as.
+    pub fn proc_arch(&mut self) -> Result<ArchitectureIdent> {
+        exe_arch(self.pid).ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))
+    }
+
+    /// Terminal Services sessions only exist on Windows.
+    pub fn session_id(&mut self) -> Result<u32> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Retrieves whether the process is still running.
+    ///
+    /// Zombies are reported as dead with their exit code.
+    pub fn state(&mut self) -> ProcessState {
+        let Some(stat) = self.proc().ok().and_then(|p| p.stat().ok()) else {
+            return ProcessState::Unknown;
+        };
+
+        match stat.state {
+            'Z' | 'X' | 'x' => ProcessState::Dead(stat.exit_code.unwrap_or(0)),
+            _ => ProcessState::Alive,
+        }
+    }
+
+    /// Queries everything that makes up the [`ProcessInfo`] of the process.
+    pub fn info(&mut self) -> Result<ProcessInfo> {
+        Ok(ProcessInfo {
+            state: self.state(),
+            ..process_info(self.pid)?
+        })
+    }
+
+    // Opened on demand, as a `Process` keeps its procfs directory open.
+    fn proc(&self) -> Result<Process> {
+        Process::new(self.pid as _)
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound))
+    }
+}
+
+/// Iterates over the processes of a process list snapshot.
+pub struct ProcessIter {
+    entries: std::vec::IntoIter<ProcessEntry>,
+}
+
+impl ProcessIter {
+    pub(super) fn snapshot(skip_system: bool, skipped: &mut SkipLog) -> Result<Self> {
+        let entries = proc_pids(skip_system, skipped)?
+            .into_iter()
+            // Processes may exit between listing and reading their name.
+            .filter_map(|pid| {
+                let comm = match std::fs::read_to_string(format!("/proc/{pid}/comm")) {
+                    Ok(comm) => comm,
+                    Err(e) => {
+                        let err = Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile);
+                        skipped.record(pid, skip_reason(pid, err, e.raw_os_error()));
+                        return None;
+                    }
+                };
+
+                Some(ProcessEntry {
+                    pid,
+                    name: comm.trim_end_matches('\n').to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            entries: entries.into_iter(),
+        })
+    }
+}
+
+impl Iterator for ProcessIter {
+    type Item = ProcessEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
@@ -3,6 +3,53 @@ use memflow::prelude::v1::*;
 
 use libc::{iovec, pid_t, sysconf, _SC_IOV_MAX};
 use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::ptr;
+use std::sync::{Arc, Once, OnceLock};
+
+use log::warn;
+
+use crate::guard::{host_addressable, host_checked_read, host_checked_write};
+use crate::os_error::set_last_os_error;
+use crate::stats::Stats;
+
+/// The system interface used to read process memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadMethod {
+    /// `process_vm_readv`, batching many ranges into a single system call.
+    ///
+    /// Falls back to [`ProcMem`](Self::ProcMem) where the system call is blocked, as in many
+    /// containers.
+    #[default]
+    VmReadv,
+    /// `pread` on a `/proc/pid/mem` handle that is kept open for the lifetime of the process.
+    ///
+    /// Writes go through the same handle with `pwrite`, as `process_vm_writev` is blocked or
+    /// rejected in the same cases as `process_vm_readv`.
+    ///
+    /// Some hardened kernels and Yama configurations allow this while rejecting `process_vm_readv`.
+    ProcMem,
+}
+
+impl ReadMethod {
+    /// Parses the `read_method` argument value.
+    pub fn from_arg(value: &str) -> Option<Self> {
+        match value.trim() {
+            "vm_readv" | "vmreadv" => Some(Self::VmReadv),
+            "procmem" => Some(Self::ProcMem),
+            _ => None,
+        }
+    }
+
+    /// The `read_method` argument value selecting this method.
+    pub fn arg(self) -> &'static str {
+        match self {
+            Self::VmReadv => "vm_readv",
+            Self::ProcMem => "procmem",
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 #[repr(transparent)]
@@ -15,13 +62,40 @@ pub struct ProcessVirtualMemory {
     pid: pid_t,
     temp_iov: Box<[IoSendVec]>,
     temp_meta: Box<[Address]>,
+    read_method: ReadMethod,
+    proc_mem: Option<Arc<File>>,
+    pub(crate) stats: Arc<Stats>,
 }
 
 impl ProcessVirtualMemory {
-    pub fn new(info: &ProcessInfo) -> Self {
+    pub fn try_new(info: &ProcessInfo, read_method: ReadMethod) -> Result<Self> {
+        let read_method = if read_method == ReadMethod::VmReadv && !vm_readv_available() {
+            warn!("process_vm_readv is blocked on this system, reading through /proc/pid/mem");
+            ReadMethod::ProcMem
+        } else {
+            read_method
+        };
+
+        let proc_mem = match read_method {
+            ReadMethod::VmReadv => None,
+            ReadMethod::ProcMem => {
+                Some(Arc::new(open_proc_mem(info.pid as pid_t).map_err(|e| {
+                    if let Some(code) = e.raw_os_error() {
+                        set_last_os_error(code);
+                    }
+
+                    if e.kind() == std::io::ErrorKind::PermissionDenied {
+                        permission_error()
+                    } else {
+                        Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(e)
+                    }
+                })?))
+            }
+        };
+
         let iov_max = unsafe { sysconf(_SC_IOV_MAX) } as usize;
 
-        Self {
+        Ok(Self {
             pid: info.pid as pid_t,
             temp_iov: vec![
                 IoSendVec(iovec {
@@ -32,23 +106,122 @@ impl ProcessVirtualMemory {
             ]
             .into_boxed_slice(),
             temp_meta: vec![Address::INVALID; iov_max].into_boxed_slice(),
+            read_method,
+            proc_mem,
+            stats: Arc::default(),
+        })
+    }
+
+    /// The method reads are carried out with.
+    pub fn read_method(&self) -> ReadMethod {
+        self.read_method
+    }
+
+    /// Checks that the process can be read at `addr`, switching to the other read method with a
+    /// warning if only that one succeeds.
+    ///
+    /// Nothing changes if neither method can read `addr`, the failure then surfaces with the
+    /// first read.
+    pub(super) fn validate(&mut self, info: &ProcessInfo, addr: Address) {
+        if self.read_raw_into(addr, &mut [0u8]).is_ok() {
+            return;
+        }
+
+        let other = match self.read_method {
+            ReadMethod::VmReadv => ReadMethod::ProcMem,
+            ReadMethod::ProcMem => ReadMethod::VmReadv,
+        };
+
+        let Ok(mut fallback) = Self::try_new(info, other) else {
+            return;
+        };
+
+        if fallback.read_raw_into(addr, &mut [0u8]).is_ok() {
+            warn!(
+                "`{}` reads of process {} failed, reading with `{}` instead",
+                self.read_method.arg(),
+                info.pid,
+                fallback.read_method.arg()
+            );
+
+            fallback.stats = self.stats.clone();
+            *self = fallback;
         }
     }
 
-    fn vm_error() -> Option<ErrorKind> {
-        let ret = match unsafe { *libc::__errno_location() } {
+    fn vm_error() -> Option<Error> {
+        let errno = unsafe { *libc::__errno_location() };
+        set_last_os_error(errno);
+
+        let ret = match errno {
             libc::EFAULT => return None,
             libc::EINVAL => ErrorKind::ArgValidation,
             libc::ENOMEM => return None,
-            libc::EPERM => ErrorKind::NotSupported, // ErrorKind::Permissions
+            libc::EPERM => return Some(permission_error()),
             libc::ESRCH => ErrorKind::ProcessNotFound,
             _ => ErrorKind::Unknown,
         };
 
-        Some(ret)
+        Some(Error(ErrorOrigin::OsLayer, ret))
     }
 }
 
+/// Opens `/proc/pid/mem` for reading and writing, or only for reading where writes to it are
+/// not allowed, as with the `proc_mem.force_override=never` kernel parameter.
+fn open_proc_mem(pid: pid_t) -> std::io::Result<File> {
+    let path = format!("/proc/{pid}/mem");
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .or_else(|_| File::open(&path))
+}
+
+/// Checks whether `process_vm_readv` can be called at all.
+///
+/// The seccomp profiles of containers, Docker's default one among them, and some WSL2 kernels
+/// reject the system call outright. A read of zero bytes only fails then, as the kernel returns
+/// before it looks up the target.
+fn vm_readv_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+
+    *AVAILABLE.get_or_init(|| {
+        let ret =
+            unsafe { libc::process_vm_readv(libc::getpid(), ptr::null(), 0, ptr::null(), 0, 0) };
+
+        if ret == -1 {
+            set_last_os_error(unsafe { *libc::__errno_location() });
+        }
+
+        ret != -1
+    })
+}
+
+/// Error for accesses the kernel denied, usually because of Yama's `ptrace_scope`.
+///
+/// memflow's error kinds can not carry a message, so the hint on how to gain access is logged
+/// with the first occurrence.
+pub(super) fn permission_error() -> Error {
+    static HINT: Once = Once::new();
+
+    let err = Error(ErrorOrigin::OsLayer, ErrorKind::Configuration);
+
+    HINT.call_once(|| {
+        let scope = std::fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope")
+            .map(|s| format!(" (kernel.yama.ptrace_scope is {})", s.trim()))
+            .unwrap_or_default();
+
+        err.log_error(format!(
+            "access to the target process was denied{scope}, \
+            set kernel.yama.ptrace_scope=0, run as root or with CAP_SYS_PTRACE, \
+            or PTRACE_ATTACH to the target first"
+        ));
+    });
+
+    err
+}
+
 // Helper trait for `process_rw` to be generic.
 trait RWSlice: core::ops::Deref<Target = [u8]> {
     /// Pass the iovecs to appropriate system call.
@@ -137,66 +310,56 @@ impl ProcessVirtualMemory {
             elem = inp.next();
 
             if elem.is_none() || iov_next.is_none() {
+                let total = cnt + 1;
                 let mut offset = 0;
 
-                // Process all iovecs, but skip one by one if we get partial results
-                loop {
-                    let cnt = cnt + 1 - offset;
-
-                    if cnt == 0 {
-                        break;
-                    }
-
+                // The system call stops at the first range that can not be transferred. That range
+                // is failed and the call repeated for the rest, so one bad range does not fail the
+                // whole batch.
+                while offset < total {
                     let libcret = unsafe {
                         T::do_rw(
                             self.pid,
                             iov_local.as_ptr().add(offset).cast(),
                             iov_remote.as_ptr().add(offset).cast(),
-                            cnt,
+                            total - offset,
                         )
                     };
 
-                    let vm_err = if libcret == -1 {
-                        Self::vm_error()
+                    self.stats.add_syscalls(1);
+
+                    // A fault on the first range fails the call, only other errors are fatal.
+                    let mut transferred = if libcret == -1 {
+                        if let Some(err) = Self::vm_error() {
+                            return Err(err);
+                        }
+
+                        0
                     } else {
-                        None
+                        libcret as usize
                     };
 
-                    match vm_err {
-                        Some(err) => return Err(Error(ErrorOrigin::OsLayer, err)),
-                        _ => {
-                            let mut remaining_written = libcret as usize + 1;
-
-                            for (liof, (_, meta)) in iov_local
-                                .iter()
-                                .take(cnt)
-                                .zip(iov_remote.iter().zip(self.temp_meta.iter()))
-                            {
-                                offset += 1;
-                                let to_write = remaining_written;
-
-                                remaining_written =
-                                    remaining_written.saturating_sub(liof.0.iov_len);
-
-                                if to_write > 0 {
-                                    if !opt_call(
-                                        out.as_deref_mut(),
-                                        CTup2(*meta, unsafe { T::from_iovec(liof.0) }),
-                                    ) {
-                                        break 'exit;
-                                    }
-                                } else {
-                                    // This will take only the first unread element and write it to the
-                                    // failed list, because it could be that only it is invalid.
-                                    if !opt_call(
-                                        out_fail.as_deref_mut(),
-                                        CTup2(*meta, unsafe { T::from_iovec(liof.0) }),
-                                    ) {
-                                        break 'exit;
-                                    }
-                                    break;
-                                }
+                    for (liov, meta) in iov_local[offset..total]
+                        .iter()
+                        .zip(&self.temp_meta[offset..total])
+                    {
+                        offset += 1;
+
+                        let data = CTup2(*meta, unsafe { T::from_iovec(liov.0) });
+
+                        // A range transferred only in part is the one the call stopped at.
+                        if transferred >= liov.0.iov_len {
+                            transferred -= liov.0.iov_len;
+
+                            if !opt_call(out.as_deref_mut(), data) {
+                                break 'exit;
+                            }
+                        } else {
+                            if !opt_call(out_fail.as_deref_mut(), data) {
+                                break 'exit;
                             }
+
+                            break;
                         }
                     }
                 }
@@ -213,19 +376,159 @@ impl ProcessVirtualMemory {
     }
 }
 
+impl ProcessVirtualMemory {
+    /// Reads each range with a separate `pread` on the `/proc/pid/mem` handle.
+    ///
+    /// Ranges that can not be read in full are passed to the failure callback.
+    fn proc_mem_read(
+        file: &File,
+        stats: &Stats,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, mut buf) in inp {
+            #[allow(clippy::unnecessary_cast)]
+            let offset = addr.to_umem() as u64;
+
+            stats.add_syscalls(1);
+
+            let keep_going = if file.read_exact_at(&mut buf, offset).is_ok() {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+            };
+
+            if !keep_going {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes each range with a separate `pwrite` on the `/proc/pid/mem` handle.
+    ///
+    /// Ranges that can not be written in full, or at all if the handle is read-only, are passed
+    /// to the failure callback.
+    fn proc_mem_write(
+        file: &File,
+        stats: &Stats,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: WriteRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, data) in inp {
+            #[allow(clippy::unnecessary_cast)]
+            let offset = addr.to_umem() as u64;
+
+            stats.add_syscalls(1);
+
+            let keep_going = match file.write_all_at(&data, offset) {
+                Ok(()) => opt_call(out.as_deref_mut(), CTup2(meta_addr, data)),
+                Err(e) => {
+                    if let Some(code) = e.raw_os_error() {
+                        set_last_os_error(code);
+                    }
+
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, data))
+                }
+            };
+
+            if !keep_going {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ProcessVirtualMemory {
+    /// Reads `buf` with a single system call of the configured read method, returning the number
+    /// of bytes read.
+    pub fn read_single(&self, addr: Address, buf: &mut [u8]) -> Result<usize> {
+        let len = self.stats.check(self.read_single_uncounted(addr, buf))?;
+        self.stats.add_read(len);
+
+        Ok(len)
+    }
+
+    fn read_single_uncounted(&self, addr: Address, buf: &mut [u8]) -> Result<usize> {
+        #[allow(clippy::unnecessary_cast)]
+        let remote = addr.to_umem() as u64;
+
+        if let Some(file) = &self.proc_mem {
+            self.stats.add_syscalls(1);
+
+            return file.read_at(buf, remote).map_err(|e| {
+                if let Some(code) = e.raw_os_error() {
+                    set_last_os_error(code);
+                }
+
+                Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+            });
+        }
+
+        if !host_addressable(addr, buf.len()) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds));
+        }
+
+        let local = iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+
+        let remote = iovec {
+            iov_base: remote as *mut c_void,
+            iov_len: buf.len(),
+        };
+
+        self.stats.add_syscalls(1);
+
+        let ret = unsafe { libc::process_vm_readv(self.pid, &local, 1, &remote, 1, 0) };
+
+        if ret == -1 {
+            Err(Self::vm_error()
+                .unwrap_or(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+}
+
 impl MemoryView for ProcessVirtualMemory {
     fn read_raw_iter<'a>(&mut self, data: ReadRawMemOps) -> Result<()> {
-        self.process_rw(data)
+        let stats = self.stats.clone();
+
+        stats.counted_read(data, |data| match &self.proc_mem {
+            Some(file) => Self::proc_mem_read(file, &stats, data),
+            // /proc/pid/mem takes 64-bit offsets, only the iovecs are limited to the host.
+            None => host_checked_read(data, |data| self.process_rw(data)),
+        })
     }
 
     fn write_raw_iter<'a>(&mut self, data: WriteRawMemOps) -> Result<()> {
-        self.process_rw(data)
+        let stats = self.stats.clone();
+
+        stats.counted_write(data, |data| match &self.proc_mem {
+            Some(file) => Self::proc_mem_write(file, &stats, data),
+            None => host_checked_write(data, |data| self.process_rw(data)),
+        })
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
         MemoryViewMetadata {
-            arch_bits: if cfg!(pointer_width = "64") { 64 } else { 32 },
-            little_endian: cfg!(target_endianess = "little"),
+            arch_bits: if cfg!(target_pointer_width = "64") {
+                64
+            } else {
+                32
+            },
+            little_endian: cfg!(target_endian = "little"),
             max_address: Address::invalid(),
             readonly: false,
             real_size: 0,
@@ -6,22 +6,350 @@ use log::error;
 
 use procfs::KernelModule;
 
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_X86_64};
+use memflow::types::size;
+
+use std::fs::File;
+use std::io::Read;
+
 use itertools::Itertools;
 
+use crate::args::{bool_arg, size_arg};
+use crate::guard::{DEFAULT_MAX_READ_SIZE, DEFAULT_MIN_ADDRESS};
+use crate::hooks::{AttachHooks, ProcessHook};
+use crate::os_error::last_os_error;
+use crate::skip::{SkipLog, SkipReason};
+use crate::tree::child_pids;
+
+pub mod iter;
+use iter::ProcessIter;
+
 pub mod mem;
-use mem::ProcessVirtualMemory;
+use mem::{ProcessVirtualMemory, ReadMethod};
 
 pub mod process;
 use process::LinuxProcess;
 
+pub mod ptrace;
+
+/// Determines the architecture from the first 20 bytes of an ELF header.
+fn elf_arch(header: &[u8; 20]) -> Option<ArchitectureIdent> {
+    if &header[..4] != b"\x7fELF" {
+        return None;
+    }
+
+    let machine = match header[5] {
+        1 => u16::from_le_bytes([header[18], header[19]]),
+        2 => u16::from_be_bytes([header[18], header[19]]),
+        _ => return None,
+    };
+
+    match (header[4], machine) {
+        (_, EM_386) => Some(ArchitectureIdent::X86(32, false)),
+        // x32 binaries run in 64-bit mode with 32-bit pointers.
+        (1, EM_X86_64) => Some(ArchitectureIdent::X86(32, false)),
+        (2, EM_X86_64) => Some(ArchitectureIdent::X86(64, false)),
+        (2, EM_AARCH64) => Some(ArchitectureIdent::AArch64(size::kb(4))),
+        _ => None,
+    }
+}
+
+/// Gathers the [`ProcessInfo`] of `pid` from procfs.
+fn process_info(pid: Pid) -> Result<ProcessInfo> {
+    let proc = procfs::process::Process::new(pid as pid_t)
+        .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?;
+
+    let command_line = proc
+        .cmdline()
+        .ok()
+        .map(|v| v.join(" ").split('\0').collect_vec().join(" "))
+        .unwrap_or_else(String::new)
+        .into();
+
+    let path = proc
+        .cmdline()
+        .ok()
+        .and_then(|l| {
+            l.get(0)
+                .map(|s| s.split('\0').next().unwrap_or("").to_string())
+        })
+        .unwrap_or_else(|| {
+            proc.status()
+                .ok()
+                .map(|s| s.name)
+                .unwrap_or_else(|| "unknown".to_string())
+        });
+
+    let name = path.split(&['/', '\\'][..]).last().unwrap().into();
+
+    let path = path.into();
+
+    let sys_arch = ArchitectureIdent::X86(64, false);
+
+    let proc_arch = exe_arch(pid).unwrap_or(sys_arch);
+
+    Ok(ProcessInfo {
+        address: (proc.pid() as umem).into(),
+        pid,
+        command_line,
+        path,
+        name,
+        sys_arch,
+        proc_arch,
+        state: ProcessState::Alive,
+        // dtb is not known/used here
+        dtb1: Address::invalid(),
+        dtb2: Address::invalid(),
+    })
+}
+
+/// Lists the ids of all processes in `/proc`, leaving out kernel threads if `skip_system` is
+/// set and recording them in `skipped`.
+fn proc_pids(skip_system: bool, skipped: &mut SkipLog) -> Result<Vec<Pid>> {
+    // Only the directory names are looked at, unlike `all_processes` no process is opened.
+    Ok(std::fs::read_dir("/proc")
+        .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|&pid| {
+            if skip_system && is_kernel_thread(pid) {
+                skipped.record(pid, SkipReason::System);
+                return false;
+            }
+
+            true
+        })
+        .collect())
+}
+
+/// Tells apart processes that exited from ones that could not be queried.
+fn skip_reason(pid: Pid, err: Error, os_error: Option<i32>) -> SkipReason {
+    if std::path::Path::new(&format!("/proc/{pid}")).exists() {
+        SkipReason::Failed(err, os_error)
+    } else {
+        SkipReason::Exited
+    }
+}
+
+/// Checks whether `pid` is a kernel thread, which has no memory maps to read.
+///
+/// Processes that exit before their flags are read are reported as kernel threads, as they can
+/// not be opened either.
+fn is_kernel_thread(pid: Pid) -> bool {
+    const PF_KTHREAD: u32 = 0x0020_0000;
+
+    procfs::process::Process::new(pid as pid_t)
+        .and_then(|proc| proc.stat())
+        .map_or(true, |stat| stat.flags & PF_KTHREAD != 0)
+}
+
+/// Determines the architecture of the executable `pid` runs.
+fn exe_arch(pid: Pid) -> Option<ArchitectureIdent> {
+    let mut header = [0u8; 20];
+
+    File::open(format!("/proc/{pid}/exe"))
+        .and_then(|mut exe| exe.read_exact(&mut header))
+        .ok()
+        .and_then(|_| elf_arch(&header))
+}
+
+/// Options applied to every process opened through [`LinuxOs`].
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessConfig {
+    pub read_method: ReadMethod,
+    /// Reads larger than this fail instead of being attempted. Within a batch only those reads
+    /// fail, the rest are carried out.
+    pub max_read_size: usize,
+    /// Reads starting below this fail instead of being attempted, zero disables the check.
+    /// Within a batch only those reads fail, the others are carried out.
+    pub min_address: usize,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            read_method: ReadMethod::default(),
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            min_address: DEFAULT_MIN_ADDRESS,
+        }
+    }
+}
+
+/// The OS layer for the running Linux system.
+///
+/// Kernel structures such as `task_struct` are out of reach from user mode, so the address of a
+/// process is its id, `Address::from(pid as umem)`. Looking a process up by either gives the same
+/// [`ProcessInfo`].
 pub struct LinuxOs {
     info: OsInfo,
     cached_modules: Vec<KernelModule>,
+    config: ProcessConfig,
+    hooks: AttachHooks,
+    /// Leave kernel threads out of process enumeration.
+    skip_system: bool,
+    skipped: SkipLog,
 }
 
 impl LinuxOs {
-    pub fn new(_: &OsArgs) -> Result<Self> {
-        Ok(Default::default())
+    pub fn new(args: &OsArgs) -> Result<Self> {
+        let read_method = match args.extra_args.get("read_method") {
+            Some(value) => ReadMethod::from_arg(value).ok_or_else(|| {
+                Error(ErrorOrigin::Args, ErrorKind::ArgValidation)
+                    .log_error(format!("invalid value for `read_method`: {value}"))
+            })?,
+            None => ReadMethod::default(),
+        };
+
+        let config = ProcessConfig {
+            read_method,
+            max_read_size: size_arg(&args.extra_args, "max_read_size", DEFAULT_MAX_READ_SIZE)?,
+            min_address: size_arg(&args.extra_args, "min_address", DEFAULT_MIN_ADDRESS)?,
+        };
+
+        Ok(Self {
+            config,
+            skip_system: bool_arg(&args.extra_args, "skip_system", false)?,
+            ..Default::default()
+        })
+    }
+
+    /// Retrieves the `errno` behind the last error returned on this thread.
+    ///
+    /// memflow errors only carry a generic kind, this is the code the system reported.
+    pub fn last_os_error(&self) -> Option<i32> {
+        last_os_error()
+    }
+
+    /// Registers `hook` to run whenever a process was opened, replacing the previous one.
+    ///
+    /// The hook runs with the info of the process once it was opened successfully, on the thread
+    /// that opened it. Clones of this instance made afterwards share it, processes that were
+    /// opened before are not affected.
+    pub fn set_attach_hook(&mut self, hook: Option<ProcessHook>) {
+        self.hooks.on_attach = hook;
+    }
+
+    /// Registers `hook` to run whenever a process is closed, replacing the previous one.
+    ///
+    /// A process counts as closed once it and all of its clones were dropped or reattached to
+    /// another process. The hook runs on the thread that let go of the last of them.
+    pub fn set_detach_hook(&mut self, hook: Option<ProcessHook>) {
+        self.hooks.on_detach = hook;
+    }
+
+    /// Takes a snapshot of the process list.
+    ///
+    /// Unlike [`Os::process_info_list`], per-process details are only queried for the entries
+    /// that ask for them.
+    pub fn processes(&mut self) -> Result<ProcessIter> {
+        self.skipped.clear();
+        ProcessIter::snapshot(self.skip_system, &mut self.skipped)
+    }
+
+    /// Starts or stops recording why processes are left out of process lists.
+    ///
+    /// Off by default. Each enumeration replaces what the previous one recorded, see
+    /// [`Self::skipped_processes`].
+    pub fn set_record_skipped(&mut self, enable: bool) {
+        self.skipped.set_enabled(enable);
+    }
+
+    /// Retrieves the processes the last enumeration left out, along with the reason.
+    ///
+    /// Kernel threads are left out with the `skip_system` option, and processes that exit or can
+    /// not be read while they are listed are left out as well. Empty unless
+    /// [`Self::set_record_skipped`] enabled recording.
+    pub fn skipped_processes(&self) -> &[(Pid, SkipReason)] {
+        self.skipped.entries()
+    }
+
+    /// Identifies this OS layer for display, `OsInfo` has no room for a name.
+    pub fn backend_name(&self) -> &'static str {
+        "native-linux"
+    }
+
+    /// Version of the OS layer, which is the crate version.
+    pub fn backend_version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// Retrieves the major, minor and patch version of the running kernel.
+    pub fn os_version(&self) -> Result<(u32, u32, u32)> {
+        let version = procfs::KernelVersion::current()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))?;
+
+        Ok((
+            version.major as u32,
+            version.minor as u32,
+            version.patch as u32,
+        ))
+    }
+
+    /// Retrieves the ids of all running processes, without opening or querying any of them.
+    ///
+    /// This is the least intrusive way to list processes, nothing but the process list itself is
+    /// read. With `skip_system` set, the flags of each process are read as well.
+    pub fn process_pids(&mut self) -> Result<Vec<Pid>> {
+        self.skipped.clear();
+        proc_pids(self.skip_system, &mut self.skipped)
+    }
+
+    /// Retrieves all processes named `name`, compared case-sensitively.
+    ///
+    /// Unlike [`Os::process_info_by_name`], every match is returned, so processes sharing a name
+    /// can be told apart by their command line or architecture.
+    pub fn processes_by_name(&mut self, name: &str) -> Result<Vec<ProcessInfo>> {
+        Ok(self
+            .process_info_list()?
+            .into_iter()
+            .filter(|p| p.name.as_ref() == name)
+            .collect())
+    }
+
+    /// Terminal Services sessions only exist on Windows.
+    pub fn current_session_id(&self) -> Result<u32> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Window titles are only supported on Windows.
+    pub fn process_by_window_title(&mut self, _title: &str) -> Result<LinuxProcess> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Retrieves the direct child processes of `pid`.
+    pub fn children(&mut self, pid: Pid) -> Result<Vec<ProcessInfo>> {
+        self.process_subtree(pid, false)
+    }
+
+    /// Retrieves all processes below `pid` in the process tree.
+    ///
+    /// Launchers often spawn the actual program as a grandchild, which can be found this way.
+    pub fn descendants(&mut self, pid: Pid) -> Result<Vec<ProcessInfo>> {
+        self.process_subtree(pid, true)
+    }
+
+    fn process_subtree(&mut self, pid: Pid, recursive: bool) -> Result<Vec<ProcessInfo>> {
+        self.skipped.clear();
+
+        let parents = procfs::process::all_processes()
+            .map_err(|e| {
+                error!("{e}");
+                Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir)
+            })?
+            .filter_map(|p| p.ok()?.stat().ok())
+            .map(|stat| (stat.pid as Pid, stat.ppid as Pid))
+            .collect::<Vec<_>>();
+
+        Ok(child_pids(&parents, pid, recursive)
+            .into_iter()
+            // Processes may exit between listing and querying them.
+            .filter_map(|pid| match process_info(pid) {
+                Ok(info) => Some(info),
+                Err(err) => {
+                    self.skipped.record(pid, skip_reason(pid, err, None));
+                    None
+                }
+            })
+            .collect())
     }
 }
 
@@ -30,6 +358,10 @@ impl Clone for LinuxOs {
         Self {
             info: self.info.clone(),
             cached_modules: vec![],
+            config: self.config,
+            skip_system: self.skip_system,
+            hooks: self.hooks.clone(),
+            skipped: self.skipped.clone(),
         }
     }
 }
@@ -45,6 +377,10 @@ impl Default for LinuxOs {
         Self {
             info,
             cached_modules: vec![],
+            config: ProcessConfig::default(),
+            skip_system: false,
+            hooks: AttachHooks::default(),
+            skipped: SkipLog::default(),
         }
     }
 }
@@ -57,73 +393,55 @@ impl Os for LinuxOs {
     ///
     /// The callback is fully opaque. We need this style so that C FFI can work seamlessly.
     fn process_address_list_callback(&mut self, mut callback: AddressCallback) -> Result<()> {
-        procfs::process::all_processes()
-            .map_err(|e| {
-                error!("{e}");
-                Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir)
-            })?
-            .filter_map(|p| p.map(|p| p.pid() as usize).ok())
-            .map(Address::from)
+        self.skipped.clear();
+
+        proc_pids(self.skip_system, &mut self.skipped)?
+            .into_iter()
+            .map(|pid| Address::from(pid as umem))
             .take_while(|a| callback.call(*a))
             .for_each(|_| {});
 
         Ok(())
     }
 
+    /// Walks the process list and calls a callback with the info of each process
+    ///
+    /// Unlike the default, processes that can not be queried are skipped instead of ending the
+    /// walk, as processes commonly exit while they are listed.
+    fn process_info_list_callback(&mut self, mut callback: ProcessInfoCallback) -> Result<()> {
+        self.skipped.clear();
+
+        for pid in proc_pids(self.skip_system, &mut self.skipped)? {
+            match process_info(pid) {
+                Ok(info) => {
+                    if !callback.call(info) {
+                        break;
+                    }
+                }
+                Err(err) => self.skipped.record(pid, skip_reason(pid, err, None)),
+            }
+        }
+
+        Ok(())
+    }
+
     /// Find process information by its internal address
     fn process_info_by_address(&mut self, address: Address) -> Result<ProcessInfo> {
         self.process_info_by_pid(address.to_umem() as _)
     }
 
     fn process_info_by_pid(&mut self, pid: Pid) -> Result<ProcessInfo> {
-        let proc = procfs::process::Process::new(pid as pid_t)
-            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?;
-
-        let command_line = proc
-            .cmdline()
-            .ok()
-            .map(|v| v.join(" ").split('\0').collect_vec().join(" "))
-            .unwrap_or_else(String::new)
-            .into();
-
-        let path = proc
-            .cmdline()
-            .ok()
-            .and_then(|l| {
-                l.get(0)
-                    .map(|s| s.split('\0').next().unwrap_or("").to_string())
-            })
-            .unwrap_or_else(|| {
-                proc.status()
-                    .ok()
-                    .map(|s| s.name)
-                    .unwrap_or_else(|| "unknown".to_string())
-            });
-
-        let name = path.split(&['/', '\\'][..]).last().unwrap().into();
-
-        let path = path.into();
-
-        Ok(ProcessInfo {
-            address: (proc.pid() as umem).into(),
-            pid,
-            command_line,
-            path,
-            name,
-            sys_arch: ArchitectureIdent::X86(64, false),
-            proc_arch: ArchitectureIdent::X86(64, false),
-            state: ProcessState::Alive,
-            // dtb is not known/used here
-            dtb1: Address::invalid(),
-            dtb2: Address::invalid(),
-        })
+        process_info(pid)
     }
 
     /// Construct a process by its info, borrowing the OS
     ///
     /// It will share the underlying memory resources
     fn process_by_info(&mut self, info: ProcessInfo) -> Result<Self::ProcessType<'_>> {
-        LinuxProcess::try_new(info)
+        let mut process = LinuxProcess::try_new(info, self.config)?;
+        process.attached = self.hooks.attach(process.info());
+
+        Ok(process)
     }
 
     /// Construct a process by its info, consuming the OS
@@ -220,3 +538,52 @@ impl Os for LinuxOs {
         &self.info
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn pid_and_address_lookups_match() {
+        let mut os = LinuxOs::new(&Default::default()).unwrap();
+        let pid = std::process::id() as Pid;
+
+        let by_address = os.process_info_by_address(Address::from(pid)).unwrap();
+        let by_pid = os.process_info_by_pid(pid).unwrap();
+
+        assert_eq!(by_address.pid, pid);
+        assert_eq!(by_address.address, Address::from(pid));
+        assert_eq!(format!("{by_address:?}"), format!("{by_pid:?}"));
+    }
+
+    #[test]
+    fn process_started_after_listing_is_found() {
+        let mut os = LinuxOs::new(&Default::default()).unwrap();
+        os.process_info_list().unwrap();
+
+        // Blocks on its stdin until it is killed.
+        let mut child = Command::new("cat").stdin(Stdio::piped()).spawn().unwrap();
+        let pid = child.id() as Pid;
+
+        // The child only shows up as `cat` once it got to execute it.
+        for _ in 0..100 {
+            match std::fs::read_to_string(format!("/proc/{pid}/comm")) {
+                Ok(comm) if comm.trim() == "cat" => break,
+                _ => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+
+        let by_address = os.process_info_by_address(Address::from(pid));
+        let by_pid = os.process_info_by_pid(pid);
+
+        child.kill().ok();
+        child.wait().ok();
+
+        let (by_address, by_pid) = (by_address.unwrap(), by_pid.unwrap());
+
+        assert_eq!(by_address.pid, pid);
+        assert_eq!(format!("{by_address:?}"), format!("{by_pid:?}"));
+    }
+}
@@ -2,13 +2,37 @@ use memflow::cglue;
 use memflow::os::process::*;
 use memflow::prelude::v1::*;
 
-use super::ProcessVirtualMemory;
+use super::mem::{permission_error, ReadMethod};
+use super::ptrace::{fs_base, thread_snapshot};
+use super::{elf_arch, ProcessConfig, ProcessVirtualMemory};
+use crate::align::aligned_read;
+use crate::cache::PageCache;
+use crate::guard::{
+    check_address, check_arch_bits, check_size, checked_read, low_checked_read, stack_addr,
+};
+use crate::hooks::Attached;
+use crate::lru::ModuleLru;
+use crate::os_error::last_os_error;
+use crate::{
+    HandleInfo, LenKind, MappedFile, MappedRegion, MemInfo, RegionPermissions, RemotePtr,
+    ResourceCounts, Stats, TebInfo, ThreadSnapshot,
+};
+
+use std::mem::size_of;
+use std::os::unix::fs::MetadataExt;
+use std::sync::Arc;
+use std::time::Duration;
 
 use libc::pid_t;
 
-use procfs::process::{MMPermissions, MMapExtension, MMapPath};
+use procfs::process::{MMPermissions, MMapExtension, MMapPath, MemoryMap, MemoryMaps};
 
 use itertools::Itertools;
+use log::warn;
+
+/// Shared objects of the .NET runtimes, `libcoreclr.so` for .NET Core and later and the
+/// Mono runtimes, matched with and without a version suffix.
+const RUNTIME_MODULES: &[&str] = &["libcoreclr.so", "libmonosgen-2.0.so", "libmono-2.0.so"];
 
 pub struct LinuxProcess {
     virt_mem: ProcessVirtualMemory,
@@ -16,6 +40,13 @@ pub struct LinuxProcess {
     info: ProcessInfo,
     cached_maps: Vec<procfs::process::MemoryMap>,
     cached_module_maps: Vec<procfs::process::MemoryMap>,
+    cached_module_archs: Vec<ArchitectureIdent>,
+    cache: Option<PageCache>,
+    page_align: bool,
+    module_lru: ModuleLru,
+    /// Runs the detach hook once the last clone is dropped.
+    pub(super) attached: Option<Arc<Attached>>,
+    config: ProcessConfig,
 }
 
 impl Clone for LinuxProcess {
@@ -26,22 +57,669 @@ impl Clone for LinuxProcess {
             info: self.info.clone(),
             cached_maps: self.cached_maps.clone(),
             cached_module_maps: self.cached_module_maps.clone(),
+            cached_module_archs: self.cached_module_archs.clone(),
+            cache: self.cache.clone(),
+            page_align: self.page_align,
+            module_lru: self.module_lru.clone(),
+            attached: self.attached.clone(),
+            config: self.config,
         }
     }
 }
 
 impl LinuxProcess {
-    pub fn try_new(info: ProcessInfo) -> Result<Self> {
-        Ok(Self {
-            virt_mem: ProcessVirtualMemory::new(&info),
+    pub fn try_new(info: ProcessInfo, config: ProcessConfig) -> Result<Self> {
+        let mut process = Self {
+            virt_mem: ProcessVirtualMemory::try_new(&info, config.read_method)?,
             proc: procfs::process::Process::new(info.pid as pid_t)
                 .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?,
             info,
             cached_maps: vec![],
             cached_module_maps: vec![],
+            cached_module_archs: vec![],
+            cache: None,
+            page_align: false,
+            module_lru: ModuleLru::default(),
+            attached: None,
+            config,
+        };
+
+        // Pseudo mappings like `[vvar]` can not be read by every method, file mappings can.
+        let probe = process.memory_maps().ok().and_then(|maps| {
+            maps.into_iter().find(|map| {
+                map.perms.contains(MMPermissions::READ) && matches!(map.pathname, MMapPath::Path(_))
+            })
+        });
+
+        if let Some(map) = probe {
+            process
+                .virt_mem
+                .validate(&process.info, Address::from(map.address.0));
+        }
+
+        Ok(process)
+    }
+
+    /// The method reads are carried out with.
+    ///
+    /// This is the configured `read_method`, unless it failed to read the process when it was
+    /// opened and the other method succeeded.
+    pub fn read_method(&self) -> ReadMethod {
+        self.virt_mem.read_method()
+    }
+
+    /// Attaches to the process described by `info` in place of the current one, as after the
+    /// target was restarted.
+    ///
+    /// The process is opened with the same configuration, the page cache keeps its time to live
+    /// and the statistics keep counting. All cached pages, maps and modules are dropped. Fails
+    /// without changing anything if the new process can not be opened or is not running.
+    ///
+    /// The attach hook the process was opened with runs for the new process, the detach hook
+    /// for the current one.
+    pub fn reattach(&mut self, info: ProcessInfo) -> Result<()> {
+        let mut process = Self::try_new(info, self.config)?;
+
+        if !matches!(process.state(), ProcessState::Alive) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound));
+        }
+
+        process.cache = self.cache.as_ref().map(|cache| PageCache::new(cache.ttl()));
+        process.page_align = self.page_align;
+        process.module_lru.set_capacity(self.module_lru.capacity());
+        process.attached = self
+            .attached
+            .as_ref()
+            .and_then(|attached| attached.hooks().attach(&process.info));
+        process.virt_mem.stats = self.virt_mem.stats.clone();
+
+        *self = process;
+
+        Ok(())
+    }
+
+    /// Serving reads from mapped module images is only supported on Windows.
+    pub fn map_module_image(&mut self, _module: &ModuleInfo) -> Result<usize> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Enables a page cache for reads, serving cached pages for up to `ttl` before refetching.
+    ///
+    /// Passing `None` disables the cache again.
+    pub fn set_page_cache(&mut self, ttl: Option<Duration>) {
+        self.cache = ttl.map(PageCache::new);
+    }
+
+    /// Rounds uncached reads out to page boundaries, reading the whole pages and passing on the
+    /// requested bytes.
+    ///
+    /// Off by default, as it reads more than requested. Reads through the page cache are always
+    /// done in whole pages.
+    pub fn set_page_align(&mut self, enable: bool) {
+        self.page_align = enable;
+    }
+
+    /// Keeps the `capacity` most recently looked up modules by name, so repeated
+    /// [`Process::module_by_name`] lookups skip walking the module list.
+    ///
+    /// Entries are revalidated against the module list whenever it is enumerated, in between a
+    /// hit returns the module as of the last enumeration. Zero, the default, disables the
+    /// cache.
+    pub fn set_module_lru(&mut self, capacity: usize) {
+        self.module_lru.set_capacity(capacity);
+    }
+
+    /// Drops all pages held by the page cache.
+    pub fn invalidate_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate();
+        }
+    }
+
+    /// Drops the cached pages overlapping `start..end`, forcing fresh reads of that range.
+    pub fn invalidate_range(&mut self, start: Address, end: Address) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate_range(start, end);
+        }
+    }
+
+    /// Cumulative counters of the memory accesses of the process, see [`Stats`].
+    pub fn stats(&self) -> &Stats {
+        &self.virt_mem.stats
+    }
+
+    /// Reads `count` consecutive values of `T` at `addr` in a single read.
+    ///
+    /// The size is checked against the maximum read size before anything is allocated, so a bogus
+    /// `count` fails with `OutOfBounds`.
+    pub fn read_array<T: Pod>(&mut self, addr: Address, count: usize) -> Result<Vec<T>> {
+        let len = count.saturating_mul(size_of::<T>());
+        check_size(len, self.config.max_read_size)?;
+
+        let mut out = (0..count).map(|_| T::zeroed()).collect::<Vec<_>>();
+        self.read_into(addr, &mut out[..]).data()?;
+
+        Ok(out)
+    }
+
+    /// Reads a length-prefixed buffer, such as the contents of a string or array object.
+    ///
+    /// The length is read as `len_kind` at `len_addr`, then that many bytes are read from the
+    /// pointer at `data_ptr_addr`. A corrupt length fails with `OutOfBounds` against the maximum
+    /// read size before anything is allocated.
+    pub fn read_sized(
+        &mut self,
+        len_addr: Address,
+        len_kind: LenKind,
+        data_ptr_addr: Address,
+    ) -> Result<Vec<u8>> {
+        let len = len_kind.read(self, len_addr)?;
+        check_size(len, self.config.max_read_size)?;
+
+        let data = RemotePtr::<u8>::read_at(self, data_ptr_addr)?;
+
+        let mut buf = vec![0u8; len];
+        self.read_raw_into(data.address, &mut buf).data()?;
+
+        Ok(buf)
+    }
+
+    /// Reads `buf` from `addr` with a single system call, bypassing the page cache.
+    ///
+    /// This is a micro-benchmark tool for measuring raw syscall latency against the batched
+    /// [`MemoryView`] path, regular code should use the [`MemoryView`] reads. Returns the number
+    /// of bytes read.
+    pub fn read_raw_uncached(&self, addr: Address, buf: &mut [u8]) -> Result<usize> {
+        check_size(buf.len(), self.config.max_read_size)?;
+        check_address(addr, self.config.min_address)?;
+        self.virt_mem.read_single(addr, buf)
+    }
+
+    /// Reads a single byte at the base of the primary module, bypassing the page cache.
+    ///
+    /// This is a cheap health check after attaching, before the main loop starts. Fails with
+    /// `Configuration` if access to the process is denied, and with `UnableToReadMemory` if the
+    /// module base is not mapped.
+    pub fn probe(&mut self) -> Result<()> {
+        let base = self.primary_module()?.base;
+
+        self.read_raw_uncached(base, &mut [0u8])
+            .map_err(|err| match (err.1, last_os_error()) {
+                // `/proc/pid/mem` reports denied access as a plain read failure.
+                (ErrorKind::UnableToReadMemory, Some(libc::EACCES | libc::EPERM)) => {
+                    permission_error()
+                }
+                _ => err,
+            })?;
+
+        Ok(())
+    }
+
+    /// Checks that the primary module matches the architecture the process is read with.
+    ///
+    /// The pointer width of [`ProcessInfo::proc_arch`] is compared against the ELF header at
+    /// the base of the primary module, so a wrong detection is caught before pointers are read
+    /// with the wrong width. Fails with `InvalidArchitecture` on a mismatch.
+    pub fn validate_arch(&mut self) -> Result<()> {
+        let base = self.primary_module()?.base;
+
+        let mut header = [0u8; 20];
+        self.read_raw_into(base, &mut header)?;
+
+        let image_bits = elf_arch(&header)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile))?
+            .into_obj()
+            .bits();
+
+        check_arch_bits(self.info.proc_arch, image_bits, base)
+    }
+
+    /// Retrieves the command line split into individual arguments.
+    ///
+    /// `/proc/pid/cmdline` is already NUL separated, so no quoting rules apply.
+    pub fn argv(&self) -> Result<Vec<String>> {
+        self.proc
+            .cmdline()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))
+    }
+
+    /// Retrieves the private, read/write heap and anonymous mappings of the process.
+    ///
+    /// This is where dynamically allocated data lives, which makes it a much smaller scan target
+    /// than the full `mapped_mem` output.
+    pub fn heap_ranges(&self) -> Vec<MemoryRange> {
+        let rw_private = MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE;
+
+        self.memory_maps()
+            .map(|maps| {
+                maps.iter()
+                    .filter(|m| matches!(m.pathname, MMapPath::Heap | MMapPath::Anonymous))
+                    .filter(|m| {
+                        m.perms.contains(rw_private) && !m.perms.contains(MMPermissions::EXECUTE)
+                    })
+                    .map(|m| {
+                        CTup3(
+                            Address::from(m.address.0),
+                            (m.address.1 - m.address.0) as umem,
+                            PageType::empty().noexec(true).write(true),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Retrieves the readable mappings overlapping `start..end`, along with their backing files.
+    ///
+    /// Unlike `mapped_mem_range`, adjacent mappings are never merged.
+    pub fn mapped_regions(&self, start: Address, end: Address) -> Result<Vec<MappedRegion>> {
+        Ok(self
+            .memory_maps()?
+            .into_iter()
+            .filter(|map| {
+                Address::from(map.address.1) > start && Address::from(map.address.0) < end
+            })
+            .filter(|map| map.perms.contains(MMPermissions::READ))
+            .map(|map| {
+                let base = Address::from(map.address.0).max(start);
+                let size = Address::from(map.address.1).min(end) - base;
+
+                MappedRegion {
+                    base,
+                    size: size as umem,
+                    page_type: PageType::empty()
+                        .noexec(!map.perms.contains(MMPermissions::EXECUTE))
+                        .write(map.perms.contains(MMPermissions::WRITE)),
+                    permissions: RegionPermissions {
+                        image: matches!(map.pathname, MMapPath::Path(_)),
+                        ..map.perms.into()
+                    },
+                    path: match map.pathname {
+                        MMapPath::Path(path) => Some(path.to_string_lossy().into_owned()),
+                        _ => None,
+                    },
+                }
+            })
+            .collect())
+    }
+
+    /// Retrieves the files mapped into the process, including its executable and libraries.
+    ///
+    /// Adjacent mappings of the same file form one range, files that are mapped multiple times
+    /// are listed once with a range per mapping.
+    pub fn mapped_files(&self) -> Result<Vec<MappedFile>> {
+        let mappings = self
+            .memory_maps()?
+            .into_iter()
+            .filter_map(|map| match map.pathname {
+                MMapPath::Path(path) => Some((path, map.inode, map.address)),
+                _ => None,
+            })
+            .coalesce(|a, b| {
+                if a.1 == b.1 && a.2 .1 == b.2 .0 {
+                    Ok((a.0, a.1, (a.2 .0, b.2 .1)))
+                } else {
+                    Err((a, b))
+                }
+            })
+            .map(|(path, _, (start, end))| {
+                (
+                    path.to_string_lossy().into_owned(),
+                    (Address::from(start), (end - start) as umem),
+                )
+            });
+
+        Ok(MappedFile::group(mappings))
+    }
+
+    /// Retrieves the base address the main executable is mapped at.
+    ///
+    /// This is the lowest mapping of `/proc/pid/exe`, which for PIE binaries is the ASLR slide.
+    pub fn image_base(&self) -> Result<Address> {
+        let inode = self.exe_inode()?;
+
+        self.memory_maps()?
+            .iter()
+            .filter(|map| matches!(map.pathname, MMapPath::Path(_)) && map.inode == inode)
+            .map(|map| Address::from(map.address.0))
+            .min()
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    /// Process environment blocks only exist on Windows, see [`Self::image_base`] instead.
+    pub fn peb_image_base(&mut self) -> Result<Address> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Looks up the module `name` like [`Process::module_by_name`], bypassing the page cache.
+    ///
+    /// The module list is enumerated anew by every lookup, but with the page cache enabled the
+    /// module headers may still come from pages cached before the module was loaded or
+    /// unloaded. Lookups through this method always read them from the target.
+    pub fn module_by_name_fresh(&mut self, name: &str) -> Result<ModuleInfo> {
+        self.module_lru.remove(name);
+
+        let cache = self.cache.take();
+        let module = self.module_by_name(name);
+        self.cache = cache;
+
+        module
+    }
+
+    /// Lists the modules whose name contains `substr`, ignoring ASCII case.
+    ///
+    /// The module list is refreshed and walked like [`Process::module_list`], but only matching
+    /// modules are collected.
+    pub fn modules_matching(&mut self, substr: &str) -> Result<Vec<ModuleInfo>> {
+        let substr = substr.to_ascii_lowercase();
+        let mut modules = vec![];
+
+        let callback = &mut |info: ModuleInfo| {
+            if info.name.to_ascii_lowercase().contains(&substr) {
+                modules.push(info);
+            }
+            true
+        };
+
+        self.module_list_callback(None, callback.into())?;
+
+        Ok(modules)
+    }
+
+    /// Resolves the export `symbol` of `module` to its address in the process.
+    ///
+    /// The name has to match exactly, mangled names included. Walking the exports stops at the
+    /// first match, and fails with `ExportNotFound` if there is none.
+    pub fn export_address(&mut self, module: &ModuleInfo, symbol: &str) -> Result<Address> {
+        let export = self.module_export_by_name(module, symbol)?;
+        Ok(module.base + export.offset)
+    }
+
+    /// Finds the module whose image contains `addr`.
+    ///
+    /// The module list is refreshed and walked like [`Process::module_list`], stopping at the
+    /// first match. Fails with `ModuleNotFound` if no module contains the address.
+    pub fn module_by_containing_address(&mut self, addr: Address) -> Result<ModuleInfo> {
+        let mut found = None;
+
+        let callback = &mut |info: ModuleInfo| {
+            if addr >= info.base && addr < info.base + info.size {
+                found = Some(info);
+                return false;
+            }
+            true
+        };
+
+        self.module_list_callback(None, callback.into())?;
+
+        found.ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    /// Returns whether the process hosts a .NET runtime, by looking for its module.
+    ///
+    /// The module list is refreshed and walked like [`Process::module_list`], stopping at the
+    /// first runtime module. Managed assemblies themselves are not listed as modules.
+    pub fn is_managed(&mut self) -> Result<bool> {
+        let mut managed = false;
+
+        let callback = &mut |info: ModuleInfo| {
+            managed = RUNTIME_MODULES.iter().any(|name| {
+                info.name
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            });
+            !managed
+        };
+
+        self.module_list_callback(None, callback.into())?;
+
+        Ok(managed)
+    }
+
+    /// Inode of the main executable, which identifies its mappings even if it was replaced on disk.
+    fn exe_inode(&self) -> Result<u64> {
+        std::fs::metadata(format!("/proc/{}/exe", self.proc.pid()))
+            .map(|meta| meta.ino())
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))
+    }
+
+    /// Retrieves the ids of all threads of the process.
+    pub fn thread_ids(&self) -> Result<Vec<u32>> {
+        Ok(self
+            .proc
+            .tasks()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?
+            .filter_map(|task| task.ok())
+            .map(|task| task.tid as u32)
+            .collect())
+    }
+
+    /// Retrieves the TLS base of the thread `tid`, which is its `fs` base on x86-64.
+    ///
+    /// The thread is briefly stopped through ptrace to read its registers, which fails if it is
+    /// already traced.
+    pub fn tls_base(&self, tid: u32) -> Result<Address> {
+        if !self.thread_ids()?.contains(&tid) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+        }
+
+        fs_base(tid as pid_t)
+    }
+
+    /// Captures the registers and up to `stack_len` bytes of the stack of the thread `tid` as one
+    /// consistent snapshot.
+    ///
+    /// The thread is seized and stopped while its registers and stack are read, bypassing the
+    /// page cache, and resumed afterwards. Other threads keep running. Fails with `NotFound` if
+    /// `tid` is not a thread of this process.
+    pub fn thread_snapshot(&mut self, tid: u32, stack_len: usize) -> Result<ThreadSnapshot> {
+        if !self.thread_ids()?.contains(&tid) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+        }
+
+        thread_snapshot(tid as pid_t, &mut self.virt_mem, stack_len)
+    }
+
+    /// Retrieves the stack range of the thread `tid`, as the base it grows down from and the
+    /// start of its mapping.
+    ///
+    /// Only the stack of the main thread is known from the memory maps. For other threads the
+    /// mapping is looked up by the stack pointer, which stops the thread briefly, see
+    /// [`Self::thread_snapshot`].
+    pub fn thread_stack(&mut self, tid: u32) -> Result<(Address, Address)> {
+        let maps = self.memory_maps()?;
+
+        let map = if tid == self.info.pid {
+            maps.iter()
+                .find(|map| matches!(map.pathname, MMapPath::Stack))
+        } else {
+            let sp = self.thread_snapshot(tid, 0)?.sp;
+            maps.iter()
+                .find(|map| Address::from(map.address.0) <= sp && sp < Address::from(map.address.1))
+        }
+        .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))?;
+
+        Ok((Address::from(map.address.1), Address::from(map.address.0)))
+    }
+
+    /// Reads `buf` from `offset` bytes below the stack base of the thread `tid`, see
+    /// [`Self::thread_stack`].
+    ///
+    /// Fails with `OutOfBounds` if the read would reach past the stack base.
+    pub fn read_stack(&mut self, tid: u32, offset: umem, buf: &mut [u8]) -> Result<()> {
+        let (base, _) = self.thread_stack(tid)?;
+        self.read_raw_into(stack_addr(base, offset, buf.len())?, buf)?;
+
+        Ok(())
+    }
+
+    /// Thread environment blocks only exist on Windows, see [`Self::tls_base`] instead.
+    pub fn teb_base(&self, _tid: u32) -> Result<Address> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Thread environment blocks only exist on Windows.
+    pub fn teb_info(&mut self, _tid: u32) -> Result<TebInfo> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Checks whether another process traces the process, such as a debugger.
+    ///
+    /// A tracer can keep `process_vm_readv` and ptrace based reads from working. Threads of this
+    /// process briefly tracing the target, as for [`Self::tls_base`], do not count.
+    pub fn is_debugged(&self) -> Result<bool> {
+        Ok(self.tracer_pid()?.is_some())
+    }
+
+    /// Retrieves the id of the tracer of the process, `None` if it is not traced by another
+    /// process.
+    ///
+    /// This is the thread that attached, which is the main thread of most debuggers.
+    pub fn tracer_pid(&self) -> Result<Option<Pid>> {
+        let status = self
+            .proc
+            .status()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))?;
+
+        // The tracer is reported by thread id, which may be any thread of this process.
+        Ok(match status.tracerpid {
+            0 => None,
+            tid if std::path::Path::new(&format!("/proc/self/task/{tid}")).exists() => None,
+            tid => Some(tid as Pid),
+        })
+    }
+
+    /// Retrieves the current memory usage of the process.
+    pub fn memory_info(&self) -> Result<MemInfo> {
+        let statm = self
+            .proc
+            .statm()
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile))?;
+
+        let page_size = procfs::page_size() as umem;
+
+        Ok(MemInfo {
+            resident: statm.resident as umem * page_size,
+            virtual_size: statm.size as umem * page_size,
         })
     }
 
+    /// Terminal Services sessions only exist on Windows.
+    pub fn session_id(&self) -> Result<u32> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Handles only exist on Windows.
+    pub fn open_handles(&self) -> Result<Vec<HandleInfo>> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Handle and GDI/USER object counts only exist on Windows.
+    pub fn resource_counts(&self) -> Result<ResourceCounts> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Rebuilds the module list from the memory maps, merging adjacent mappings of each file.
+    fn refresh_module_maps(&mut self) -> Result<()> {
+        self.cached_maps = self.memory_maps()?;
+
+        self.cached_module_maps = self
+            .cached_maps
+            .iter()
+            .filter(|map| matches!(map.pathname, MMapPath::Path(_)))
+            .cloned()
+            .coalesce(|m1, m2| {
+                if m1.address.1 == m2.address.0
+                    // When the file gets mapped in memory, offsets change.
+                    // && m2.offset - m1.offset == m1.address.1 - m1.address.0
+                    && m1.dev == m2.dev
+                    && m1.inode == m2.inode
+                {
+                    Ok(procfs::process::MemoryMap {
+                        address: (m1.address.0, m2.address.1),
+                        perms: MMPermissions::NONE,
+                        offset: m1.offset,
+                        dev: m1.dev,
+                        inode: m1.inode,
+                        pathname: m1.pathname,
+                        extension: MMapExtension::default(),
+                    })
+                } else {
+                    Err((m1, m2))
+                }
+            })
+            .collect();
+
+        self.cached_module_archs = (0..self.cached_module_maps.len())
+            .map(|i| self.module_arch(Address::from(self.cached_module_maps[i].address.0)))
+            .collect();
+
+        // Modules keep their index unless the maps in front of them changed.
+        let mut lru = core::mem::take(&mut self.module_lru);
+
+        lru.revalidate(|cached| {
+            let is_cached = |map: &MemoryMap| {
+                Self::mmap_path_to_name_string(&map.pathname).as_ref() == cached.name.as_ref()
+            };
+
+            let index = match self
+                .cached_module_maps
+                .get(cached.address.to_umem() as usize)
+            {
+                Some(map) if is_cached(map) => cached.address.to_umem() as usize,
+                _ => self.cached_module_maps.iter().position(is_cached)?,
+            };
+
+            self.module_by_address(Address::from(index), self.cached_module_archs[index])
+                .ok()
+        });
+
+        self.module_lru = lru;
+
+        Ok(())
+    }
+
+    /// Reads the memory maps of the process.
+    ///
+    /// If procfs fails to parse the maps, they are parsed again line by line, skipping only the
+    /// lines that can not be parsed.
+    fn memory_maps(&self) -> Result<Vec<MemoryMap>> {
+        if let Ok(maps) = self.proc.maps() {
+            return Ok(maps.memory_maps);
+        }
+
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.proc.pid()))
+            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?;
+
+        let (parsed, skipped): (Vec<_>, Vec<_>) = maps
+            .lines()
+            .map(|line| MemoryMaps::from_reader(line.as_bytes()))
+            .partition(|maps| maps.is_ok());
+
+        if !skipped.is_empty() {
+            warn!("skipped {} unparseable maps lines", skipped.len());
+        }
+
+        Ok(parsed
+            .into_iter()
+            .flatten()
+            .flat_map(|maps| maps.memory_maps)
+            .collect())
+    }
+
+    /// Determines the architecture of the module mapped at `base` from its ELF header.
+    ///
+    /// Mappings that are not ELF images are reported with the process architecture.
+    fn module_arch(&mut self, base: Address) -> ArchitectureIdent {
+        let mut header = [0u8; 20];
+
+        self.virt_mem
+            .read_raw_into(base, &mut header)
+            .ok()
+            .and_then(|_| elf_arch(&header))
+            .unwrap_or(self.info.proc_arch)
+    }
+
     pub fn mmap_path_to_name_string(path: &MMapPath) -> ReprCString {
         match path {
             MMapPath::Path(buf) => buf
@@ -88,54 +766,25 @@ impl Process for LinuxProcess {
     ///
     /// # Arguments
     /// * `target_arch` - sets which architecture to retrieve the modules for (if emulated). Choose
-    /// between `Some(ProcessInfo::sys_arch())`, and `Some(ProcessInfo::proc_arch())`. `None` for all.
+    ///   between `Some(ProcessInfo::sys_arch())`, and `Some(ProcessInfo::proc_arch())`. `None` for
+    ///   all. Each module is matched by the architecture of its own ELF header, so
+    ///   `Some(proc_arch)` returns the modules of a 32-bit process on a 64-bit system.
     /// * `callback` - where to pass each matching module to. This is an opaque callback.
     fn module_address_list_callback(
         &mut self,
         target_arch: Option<&ArchitectureIdent>,
         mut callback: ModuleAddressCallback,
     ) -> Result<()> {
-        self.cached_maps = self
-            .proc
-            .maps()
-            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?
-            .memory_maps;
-
-        self.cached_module_maps = self
-            .cached_maps
-            .iter()
-            .filter(|map| matches!(map.pathname, MMapPath::Path(_)))
-            .cloned()
-            .coalesce(|m1, m2| {
-                if m1.address.1 == m2.address.0
-                    // When the file gets mapped in memory, offsets change.
-                    // && m2.offset - m1.offset == m1.address.1 - m1.address.0
-                    && m1.dev == m2.dev
-                    && m1.inode == m2.inode
-                {
-                    Ok(procfs::process::MemoryMap {
-                        address: (m1.address.0, m2.address.1),
-                        perms: MMPermissions::NONE,
-                        offset: m1.offset,
-                        dev: m1.dev,
-                        inode: m1.inode,
-                        pathname: m1.pathname,
-                        extension: MMapExtension::default(),
-                    })
-                } else {
-                    Err((m1, m2))
-                }
-            })
-            .collect();
+        self.refresh_module_maps()?;
 
-        self.cached_module_maps
+        self.cached_module_archs
             .iter()
             .enumerate()
-            .filter(|_| target_arch.is_none() || Some(&self.info().sys_arch) == target_arch)
-            .take_while(|(i, _)| {
+            .filter(|(_, arch)| target_arch.is_none() || Some(*arch) == target_arch)
+            .take_while(|(i, arch)| {
                 callback.call(ModuleAddressInfo {
                     address: Address::from(*i as u64),
-                    arch: self.info.proc_arch,
+                    arch: **arch,
                 })
             })
             .for_each(|_| {});
@@ -153,14 +802,16 @@ impl Process for LinuxProcess {
         address: Address,
         architecture: ArchitectureIdent,
     ) -> Result<ModuleInfo> {
-        if architecture != self.info.sys_arch {
+        // TODO: create cached_module_maps if its empty
+
+        let index = address.to_umem() as usize;
+
+        if self.cached_module_archs.get(index) != Some(&architecture) {
             return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
         }
 
-        // TODO: create cached_module_maps if its empty
-
         self.cached_module_maps
-            .get(address.to_umem() as usize)
+            .get(index)
             .map(|map| ModuleInfo {
                 address,
                 parent_process: self.info.address,
@@ -168,11 +819,46 @@ impl Process for LinuxProcess {
                 size: (map.address.1 - map.address.0) as umem,
                 name: Self::mmap_path_to_name_string(&map.pathname),
                 path: Self::mmap_path_to_path_string(&map.pathname),
-                arch: self.info.sys_arch,
+                arch: architecture,
             })
             .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
     }
 
+    /// Finds a module by its name, trying the module name cache first if no architecture is
+    /// given, see [`set_module_lru`](LinuxProcess::set_module_lru).
+    fn module_by_name_arch(
+        &mut self,
+        name: &str,
+        architecture: Option<&ArchitectureIdent>,
+    ) -> Result<ModuleInfo> {
+        if architecture.is_none() {
+            if let Some(info) = self.module_lru.get(name) {
+                return Ok(info);
+            }
+        }
+
+        let mut found = None;
+
+        let callback = &mut |info: ModuleInfo| {
+            if info.name.as_ref() == name {
+                found = Some(info);
+                return false;
+            }
+            true
+        };
+
+        self.module_list_callback(architecture, callback.into())?;
+
+        let info = found.ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))?;
+
+        // Lookups for one architecture may skip a module of the same name listed before it.
+        if architecture.is_none() {
+            self.module_lru.insert(info.clone());
+        }
+
+        Ok(info)
+    }
+
     fn module_import_list_callback(
         &mut self,
         info: &ModuleInfo,
@@ -201,8 +887,15 @@ impl Process for LinuxProcess {
     ///
     /// This will generally be for the initial executable that was run
     fn primary_module_address(&mut self) -> Result<Address> {
-        // TODO: Is it always 0th mod?
-        Ok(Address::from(0))
+        let inode = self.exe_inode()?;
+
+        self.refresh_module_maps()?;
+
+        self.cached_module_maps
+            .iter()
+            .position(|map| map.inode == inode)
+            .map(|i| Address::from(i as u64))
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
     }
 
     /// Retrieves the process info
@@ -211,8 +904,17 @@ impl Process for LinuxProcess {
     }
 
     /// Retrieves the state of the process
+    ///
+    /// Zombies are reported as dead with their exit code.
     fn state(&mut self) -> ProcessState {
-        ProcessState::Unknown
+        let Ok(stat) = self.proc.stat() else {
+            return ProcessState::Unknown;
+        };
+
+        match stat.state {
+            'Z' | 'X' | 'x' => ProcessState::Dead(stat.exit_code.unwrap_or(0)),
+            _ => ProcessState::Alive,
+        }
     }
 
     /// Changes the dtb this process uses for memory translations.
@@ -228,12 +930,8 @@ impl Process for LinuxProcess {
         end: Address,
         out: MemoryRangeCallback,
     ) {
-        if let Ok(maps) = self
-            .proc
-            .maps()
-            .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))
-        {
-            self.cached_maps = maps.memory_maps;
+        if let Ok(maps) = self.memory_maps() {
+            self.cached_maps = maps;
 
             self.cached_maps
                 .iter()
@@ -281,14 +979,28 @@ impl Process for LinuxProcess {
 
 impl MemoryView for LinuxProcess {
     fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
-        self.virt_mem.read_raw_iter(data)
+        let min_address = self.config.min_address;
+
+        checked_read(data, self.config.max_read_size, |data| {
+            low_checked_read(data, min_address, |data| match &mut self.cache {
+                Some(cache) => cache.read(&mut self.virt_mem, data),
+                None if self.page_align => aligned_read(&mut self.virt_mem, data),
+                None => self.virt_mem.read_raw_iter(data),
+            })
+        })
     }
 
     fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
-        self.virt_mem.write_raw_iter(data)
+        match &mut self.cache {
+            Some(cache) => cache.write(&mut self.virt_mem, data),
+            None => self.virt_mem.write_raw_iter(data),
+        }
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
-        self.virt_mem.metadata()
+        MemoryViewMetadata {
+            arch_bits: self.info.proc_arch.into_obj().bits(),
+            ..self.virt_mem.metadata()
+        }
     }
 }
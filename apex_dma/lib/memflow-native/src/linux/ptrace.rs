@@ -0,0 +1,112 @@
+use memflow::prelude::v1::*;
+
+use libc::{c_long, c_uint, c_void, pid_t};
+
+use std::mem::MaybeUninit;
+use std::ptr;
+
+use super::mem::permission_error;
+use crate::os_error::set_last_os_error;
+use crate::ThreadSnapshot;
+
+unsafe fn ptrace(request: c_uint, pid: pid_t, data: usize) -> c_long {
+    libc::ptrace(request, pid, ptr::null_mut::<c_void>(), data as *mut c_void)
+}
+
+fn errno() -> i32 {
+    unsafe { *libc::__errno_location() }
+}
+
+/// Maps the errno of a failed `PTRACE_SEIZE`.
+fn seize_error(errno: i32) -> Error {
+    set_last_os_error(errno);
+
+    match errno {
+        libc::EPERM => permission_error(),
+        libc::ESRCH => Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound),
+        _ => Error(ErrorOrigin::OsLayer, ErrorKind::Unknown),
+    }
+}
+
+/// Reads the `fs` base of the thread `tid`, which points at its TLS block on x86-64.
+///
+/// The thread is seized and stopped for the duration of the register read.
+#[cfg(target_arch = "x86_64")]
+pub fn fs_base(tid: pid_t) -> Result<Address> {
+    with_stopped(tid, |regs| Address::from(regs.fs_base))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn fs_base(_tid: pid_t) -> Result<Address> {
+    Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+}
+
+/// Captures the registers and up to `stack_len` bytes of the stack of the thread `tid`.
+///
+/// The thread is seized and stays stopped until the stack was read from `mem`.
+#[cfg(target_arch = "x86_64")]
+pub fn thread_snapshot(
+    tid: pid_t,
+    mem: &mut impl MemoryView,
+    stack_len: usize,
+) -> Result<ThreadSnapshot> {
+    with_stopped(tid, |regs| {
+        let sp = Address::from(regs.rsp);
+
+        ThreadSnapshot {
+            thread_id: tid as u32,
+            ip: Address::from(regs.rip),
+            sp,
+            fp: Address::from(regs.rbp),
+            stack: ThreadSnapshot::read_stack(mem, sp, stack_len),
+        }
+    })
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn thread_snapshot(
+    _tid: pid_t,
+    _mem: &mut impl MemoryView,
+    _stack_len: usize,
+) -> Result<ThreadSnapshot> {
+    Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+}
+
+/// Seizes and stops the thread `tid`, and passes its registers to `f` before detaching again.
+#[cfg(target_arch = "x86_64")]
+fn with_stopped<T>(tid: pid_t, f: impl FnOnce(&libc::user_regs_struct) -> T) -> Result<T> {
+    if unsafe { ptrace(libc::PTRACE_SEIZE, tid, 0) } == -1 {
+        return Err(seize_error(errno()));
+    }
+
+    let mut regs = MaybeUninit::<libc::user_regs_struct>::uninit();
+    let mut status = 0;
+
+    let ret = unsafe {
+        if ptrace(libc::PTRACE_INTERRUPT, tid, 0) == 0
+            && libc::waitpid(tid, &mut status, libc::__WALL) == tid
+        {
+            ptrace(libc::PTRACE_GETREGS, tid, regs.as_mut_ptr() as usize)
+        } else {
+            -1
+        }
+    };
+
+    // Set before detaching, which overwrites errno.
+    if ret == -1 {
+        set_last_os_error(errno());
+    }
+
+    let value = (ret != -1).then(|| f(unsafe { regs.assume_init_ref() }));
+
+    // A signal delivery stop may have come first, its signal has to be passed on.
+    let data = if libc::WIFSTOPPED(status) && status >> 16 == 0 {
+        libc::WSTOPSIG(status) as usize
+    } else {
+        0
+    };
+
+    unsafe { ptrace(libc::PTRACE_DETACH, tid, data) };
+
+    value.ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::Unknown))
+}
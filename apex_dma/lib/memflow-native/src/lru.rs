@@ -0,0 +1,140 @@
+use memflow::prelude::v1::*;
+
+/// A small least recently used cache of modules by name.
+///
+/// Entries are kept in order of use, so lookups and evictions are linear in the capacity, which
+/// is meant to stay in the single digits. A capacity of zero disables the cache.
+#[derive(Clone, Default)]
+pub(crate) struct ModuleLru {
+    capacity: usize,
+    entries: Vec<ModuleInfo>,
+}
+
+impl ModuleLru {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the capacity, evicting the least recently used entries that no longer fit.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+    }
+
+    /// Returns the module `name`, marking it as most recently used.
+    pub fn get(&mut self, name: &str) -> Option<ModuleInfo> {
+        let index = self
+            .entries
+            .iter()
+            .position(|info| info.name.as_ref() == name)?;
+
+        let info = self.entries.remove(index);
+        self.entries.insert(0, info.clone());
+
+        Some(info)
+    }
+
+    /// Adds `info` as the most recently used entry, evicting the least recently used one if full.
+    pub fn insert(&mut self, info: ModuleInfo) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&info.name);
+
+        self.entries.truncate(self.capacity - 1);
+        self.entries.insert(0, info);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|info| info.name.as_ref() != name);
+    }
+
+    /// Replaces every entry with the result of `revalidate`, dropping those it returns `None` for.
+    #[cfg(target_os = "linux")]
+    pub fn revalidate(&mut self, mut revalidate: impl FnMut(&ModuleInfo) -> Option<ModuleInfo>) {
+        self.entries = self.entries.iter().filter_map(&mut revalidate).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(name: &str) -> ModuleInfo {
+        ModuleInfo {
+            address: Address::NULL,
+            parent_process: Address::INVALID,
+            base: Address::NULL,
+            size: 0,
+            name: name.into(),
+            path: name.into(),
+            arch: ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    fn names(lru: &ModuleLru) -> Vec<&str> {
+        lru.entries.iter().map(|info| info.name.as_ref()).collect()
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let mut lru = ModuleLru::default();
+        lru.set_capacity(3);
+
+        for name in ["a", "b", "c"] {
+            lru.insert(module(name));
+        }
+
+        assert_eq!(names(&lru), ["c", "b", "a"]);
+
+        // Using `a` makes `b` the least recently used entry.
+        assert_eq!(lru.get("a").map(|info| info.name), Some("a".into()));
+        lru.insert(module("d"));
+
+        assert_eq!(names(&lru), ["d", "a", "c"]);
+        assert!(lru.get("b").is_none());
+    }
+
+    #[test]
+    fn reinserting_replaces_the_entry() {
+        let mut lru = ModuleLru::default();
+        lru.set_capacity(2);
+
+        lru.insert(module("a"));
+        lru.insert(module("b"));
+        lru.insert(ModuleInfo {
+            size: 0x1000,
+            ..module("a")
+        });
+
+        assert_eq!(names(&lru), ["a", "b"]);
+        assert_eq!(lru.get("a").map(|info| info.size), Some(0x1000));
+    }
+
+    #[test]
+    fn zero_capacity_disables_the_cache() {
+        let mut lru = ModuleLru::default();
+
+        lru.insert(module("a"));
+
+        assert_eq!(lru.capacity(), 0);
+        assert!(lru.get("a").is_none());
+    }
+
+    #[test]
+    fn shrinking_evicts_the_least_recently_used_entries() {
+        let mut lru = ModuleLru::default();
+        lru.set_capacity(3);
+
+        for name in ["a", "b", "c"] {
+            lru.insert(module(name));
+        }
+
+        lru.set_capacity(1);
+        assert_eq!(names(&lru), ["c"]);
+
+        lru.remove("c");
+        assert!(names(&lru).is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use memflow::prelude::v1::*;
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Memory at `0..len` that fails every access touching `fail`, records the address of every read
+/// and write, and splits reads at page boundaries the way the page cache does.
+pub(crate) struct MockMemory {
+    pub data: Vec<u8>,
+    pub fail: Option<Address>,
+    pub reads: Vec<Address>,
+    pub writes: Vec<Address>,
+}
+
+impl MockMemory {
+    pub fn new(len: usize, fail: Option<Address>) -> Self {
+        Self {
+            data: vec![0; len],
+            fail,
+            reads: vec![],
+            writes: vec![],
+        }
+    }
+
+    fn fails(&self, addr: Address, len: usize) -> bool {
+        addr.to_umem() as usize + len > self.data.len()
+            || self
+                .fail
+                .is_some_and(|fail| fail >= addr && fail < addr + len)
+    }
+}
+
+impl MemoryView for MockMemory {
+    fn read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+    ) -> Result<()> {
+        for CTup3(mut addr, mut meta_addr, buf) in inp {
+            self.reads.push(addr);
+
+            let mut rest = Some(buf);
+
+            while let Some(buf) = rest.take() {
+                let offset = addr.to_umem() as usize % PAGE_SIZE;
+
+                let (head, tail) = buf.split_at((PAGE_SIZE - offset) as umem);
+                rest = tail;
+
+                let Some(mut head) = head else {
+                    continue;
+                };
+
+                let len = head.len();
+
+                let keep_going = if self.fails(addr, len) {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, head))
+                } else {
+                    let start = addr.to_umem() as usize;
+                    head.copy_from_slice(&self.data[start..start + len]);
+                    opt_call(out.as_deref_mut(), CTup2(meta_addr, head))
+                };
+
+                if !keep_going {
+                    return Ok(());
+                }
+
+                addr += len;
+                meta_addr += len;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: WriteRawMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, data) in inp {
+            self.writes.push(addr);
+
+            let keep_going = if self.fails(addr, data.len()) {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, data))
+            } else {
+                let start = addr.to_umem() as usize;
+                self.data[start..start + data.len()].copy_from_slice(&data);
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, data))
+            };
+
+            if !keep_going {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        MemoryViewMetadata {
+            max_address: Address::from(self.data.len() as umem - 1),
+            real_size: self.data.len() as umem,
+            readonly: false,
+            little_endian: true,
+            arch_bits: 64,
+        }
+    }
+}
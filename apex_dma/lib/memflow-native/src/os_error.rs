@@ -0,0 +1,18 @@
+use std::cell::Cell;
+
+thread_local! {
+    static LAST_OS_ERROR: Cell<Option<i32>> = const { Cell::new(None) };
+}
+
+/// Remembers the raw OS error code behind an error that is about to be returned.
+///
+/// memflow's [`Error`](memflow::error::Error) can not carry the code itself, so it is kept per
+/// thread, the same way `errno` and `GetLastError` work.
+pub(crate) fn set_last_os_error(code: i32) {
+    LAST_OS_ERROR.with(|last| last.set(Some(code)));
+}
+
+/// Retrieves the code stored by the last [`set_last_os_error`] call on this thread.
+pub(crate) fn last_os_error() -> Option<i32> {
+    LAST_OS_ERROR.with(|last| last.get())
+}
@@ -0,0 +1,271 @@
+use memflow::prelude::v1::*;
+
+use crate::guard::check_size;
+use crate::{ModuleSection, SectionFlags};
+
+/// Granularity at which [`module_code_hash`] reads sections.
+const PAGE_SIZE: usize = 0x1000;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Translates a relative virtual address of `module` into an absolute address.
+pub fn rva_to_va(module: &ModuleInfo, rva: umem) -> Address {
+    module.base + rva
+}
+
+/// Translates an absolute address into a relative virtual address of `module`.
+///
+/// Fails with `OutOfBounds` if `va` lies outside of the module image.
+pub fn va_to_rva(module: &ModuleInfo, va: Address) -> Result<umem> {
+    if va < module.base || va >= module.base + module.size {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds));
+    }
+
+    Ok((va - module.base) as umem)
+}
+
+/// Reads each `(rva, buffer)` pair of `reads` at that offset into the module `name`.
+///
+/// The module is looked up once for the whole batch, and all buffers are read in a single batch
+/// after that. Fails if any of the reads fails, the buffers of the reads that succeeded are filled
+/// regardless.
+pub fn read_module_offsets(
+    process: &mut (impl Process + MemoryView),
+    name: &str,
+    reads: &mut [(umem, &mut [u8])],
+) -> Result<()> {
+    let module = process.module_by_name(name)?;
+
+    let mut ops = reads
+        .iter_mut()
+        .map(|(rva, buf)| CTup2(rva_to_va(&module, *rva), (&mut **buf).into()))
+        .collect::<Vec<ReadData>>();
+
+    process.read_raw_list(&mut ops).data()
+}
+
+/// Retrieves the ASLR slide of `module`, its runtime base minus the preferred `ImageBase`.
+///
+/// The `ImageBase` is read from the PE headers of the mapped image, which works for both PE32
+/// and PE32+ modules regardless of the architecture of the process.
+pub fn module_aslr_slide(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<imem> {
+    let image_base = preferred_image_base(mem, module.base)?;
+
+    Ok(module.base.to_umem().wrapping_sub(image_base) as imem)
+}
+
+/// Lists the sections of `module` along with their `Characteristics`, read from the section
+/// table of the mapped image.
+pub fn module_sections(
+    mem: &mut impl MemoryView,
+    module: &ModuleInfo,
+) -> Result<Vec<ModuleSection>> {
+    let (nt, headers) = nt_headers(mem, module.base)?;
+
+    let count = u16::from_le_bytes([headers[6], headers[7]]) as usize;
+    let optional_size = u16::from_le_bytes([headers[0x14], headers[0x15]]) as umem;
+
+    let mut table = vec![0u8; count * 0x28];
+    mem.read_raw_into(nt + 0x18 + optional_size, &mut table)?;
+
+    Ok(table
+        .chunks(0x28)
+        .map(|section| {
+            let field =
+                |offset: usize| u32::from_le_bytes(section[offset..offset + 4].try_into().unwrap());
+
+            let name_len = section[..8].iter().position(|&c| c == 0).unwrap_or(8);
+            let characteristics = field(0x24);
+
+            ModuleSection {
+                info: SectionInfo {
+                    name: String::from_utf8_lossy(&section[..name_len])
+                        .as_ref()
+                        .into(),
+                    base: module.base + field(0x0c) as umem,
+                    size: field(0x08) as umem,
+                },
+                characteristics,
+                flags: SectionFlags::from_characteristics(characteristics),
+            }
+        })
+        .collect())
+}
+
+/// Hashes the executable sections of `module`, to tell builds of a module apart.
+///
+/// The sections are hashed in order with 64-bit FNV-1a, a page at a time through a single
+/// buffer. Pointers the loader relocates are hashed as zero, so the hash only changes along with
+/// the code and not with where the module was loaded. Writable sections are left out even if they
+/// are executable, as their contents change at runtime. Code patched at runtime, such as hooks,
+/// changes the hash.
+pub fn module_code_hash(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<u64> {
+    let relocations = relocations(mem, module)?;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut chunk = vec![0u8; PAGE_SIZE];
+
+    for section in module_sections(mem, module)? {
+        if !section.flags.executable || section.flags.writable {
+            continue;
+        }
+
+        let start = (section.info.base - module.base) as umem;
+        let end = start + section.info.size;
+
+        let mut rva = start;
+
+        while rva < end {
+            let len = (end - rva).min(PAGE_SIZE as umem) as usize;
+            let chunk = &mut chunk[..len];
+
+            mem.read_raw_into(module.base + rva, chunk)?;
+
+            // Relocations are sorted, so only the ones around the chunk are visited.
+            let first = relocations.partition_point(|&(at, width)| at + width <= rva);
+
+            for &(at, width) in &relocations[first..] {
+                if at >= rva + len as umem {
+                    break;
+                }
+
+                for byte in at.max(rva)..(at + width).min(rva + len as umem) {
+                    chunk[(byte - rva) as usize] = 0;
+                }
+            }
+
+            hash = chunk.iter().fold(hash, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            });
+
+            rva += len as umem;
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Retrieves the pointer width of the image mapped at `base` from the magic of its optional
+/// header.
+#[cfg(target_os = "windows")]
+pub(crate) fn image_bits(mem: &mut impl MemoryView, base: Address) -> Result<u8> {
+    let (_, headers) = nt_headers(mem, base)?;
+    let optional = &headers[0x18..];
+
+    match u16::from_le_bytes([optional[0], optional[1]]) {
+        0x10b => Ok(32),
+        0x20b => Ok(64),
+        _ => Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)),
+    }
+}
+
+/// Lists the pointers the loader relocates in the image of `module`, sorted by their relative
+/// virtual address and along with their width, read from its base relocation table.
+fn relocations(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<Vec<(umem, umem)>> {
+    let (nt, headers) = nt_headers(mem, module.base)?;
+
+    let optional_size = u16::from_le_bytes([headers[0x14], headers[0x15]]) as umem;
+    let optional = &headers[0x18..];
+
+    // The data directories follow the fields the two optional header formats differ in.
+    let directories = match u16::from_le_bytes([optional[0], optional[1]]) {
+        0x10b => 0x60,
+        0x20b => 0x70,
+        _ => return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)),
+    };
+
+    // `IMAGE_DIRECTORY_ENTRY_BASERELOC`, missing from optional headers cut short.
+    let entry = directories + 5 * 8;
+
+    if entry + 8 > optional_size {
+        return Ok(vec![]);
+    }
+
+    let mut directory = [0u8; 8];
+    mem.read_raw_into(nt + 0x18 + entry, &mut directory)?;
+
+    let rva = u32::from_le_bytes(directory[..4].try_into().unwrap()) as umem;
+    let size = u32::from_le_bytes(directory[4..].try_into().unwrap()) as usize;
+
+    if rva == 0 || size == 0 {
+        return Ok(vec![]);
+    }
+
+    // The size comes from the headers in the target's memory, so a corrupt one must not decide
+    // the allocation. The table lies within the image.
+    check_size(size, module.size.saturating_sub(rva) as usize)?;
+
+    let mut table = vec![0u8; size];
+    mem.read_raw_into(module.base + rva, &mut table)?;
+
+    let mut relocations = vec![];
+    let mut block = &table[..];
+
+    // Each block holds the page it applies to, its own size, and a 16-bit entry per relocation.
+    while block.len() >= 8 {
+        let page = u32::from_le_bytes(block[..4].try_into().unwrap()) as umem;
+        let block_size = u32::from_le_bytes(block[4..8].try_into().unwrap()) as usize;
+
+        if block_size < 8 || block_size > block.len() {
+            break;
+        }
+
+        for entry in block[8..block_size].chunks_exact(2) {
+            let entry = u16::from_le_bytes([entry[0], entry[1]]);
+            let offset = (entry & 0xfff) as umem;
+
+            match entry >> 12 {
+                // `IMAGE_REL_BASED_HIGHLOW`
+                3 => relocations.push((page + offset, 4)),
+                // `IMAGE_REL_BASED_DIR64`
+                10 => relocations.push((page + offset, 8)),
+                // Padding and the rare types of other architectures.
+                _ => {}
+            }
+        }
+
+        block = &block[block_size..];
+    }
+
+    relocations.sort_unstable();
+
+    Ok(relocations)
+}
+
+/// Reads the `ImageBase` field of the optional header of the image mapped at `base`.
+fn preferred_image_base(mem: &mut impl MemoryView, base: Address) -> Result<umem> {
+    let (_, headers) = nt_headers(mem, base)?;
+    let optional = &headers[0x18..];
+
+    match u16::from_le_bytes([optional[0], optional[1]]) {
+        // PE32 has an additional `BaseOfData` field in front of its 32-bit `ImageBase`.
+        0x10b => Ok(u32::from_le_bytes(optional[0x1c..0x20].try_into().unwrap()) as umem),
+        0x20b => Ok(u64::from_le_bytes(optional[0x18..0x20].try_into().unwrap()) as umem),
+        _ => Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)),
+    }
+}
+
+/// Reads the signature, the file header, and the optional header up to the end of a 64-bit
+/// `ImageBase` of the image mapped at `base`, along with their address.
+fn nt_headers(mem: &mut impl MemoryView, base: Address) -> Result<(Address, [u8; 0x38])> {
+    let invalid = || Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile);
+
+    let mut dos = [0u8; 0x40];
+    mem.read_raw_into(base, &mut dos)?;
+
+    if &dos[..2] != b"MZ" {
+        return Err(invalid());
+    }
+
+    let nt = base + u32::from_le_bytes(dos[0x3c..0x40].try_into().unwrap()) as umem;
+
+    let mut headers = [0u8; 0x38];
+    mem.read_raw_into(nt, &mut headers)?;
+
+    if &headers[..4] != b"PE\0\0" {
+        return Err(invalid());
+    }
+
+    Ok((nt, headers))
+}
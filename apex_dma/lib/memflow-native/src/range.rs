@@ -0,0 +1,121 @@
+use memflow::prelude::v1::*;
+
+/// Granularity at which [`read_range_detailed`] tells readable and unreadable memory apart.
+const PAGE_SIZE: usize = 0x1000;
+
+/// The outcome of [`read_range_detailed`].
+pub struct RangeRead {
+    /// Readable parts of the range with their contents, in ascending order.
+    pub segments: Vec<(Address, Vec<u8>)>,
+    /// Start and end of every part that was not mapped or could not be read, in ascending order.
+    pub gaps: Vec<(Address, Address)>,
+}
+
+/// Reads `start..end`, reporting which parts could be read instead of zero filling the others.
+///
+/// Only memory reported by [`Process::mapped_mem_range`] is read, one batch per mapped region.
+/// Each region is read page by page, so pages that fail within a region, such as guard pages,
+/// become gaps of their own.
+pub fn read_range_detailed(
+    process: &mut (impl Process + MemoryView),
+    start: Address,
+    end: Address,
+) -> Result<RangeRead> {
+    let mut read = RangeRead {
+        segments: vec![],
+        gaps: vec![],
+    };
+
+    let mut regions = process
+        .mapped_mem_range_vec(0, start, end)
+        .into_iter()
+        .map(|CTup3(base, size, _)| (base.max(start), (base + size).min(end)))
+        .filter(|(base, end)| base < end)
+        .collect::<Vec<_>>();
+
+    regions.sort_unstable_by_key(|&(base, _)| base);
+
+    let mut cursor = start;
+
+    for (base, region_end) in regions {
+        if cursor < base {
+            read.gaps.push((cursor, base));
+        }
+
+        read_region(process, base, region_end, &mut read)?;
+        cursor = cursor.max(region_end);
+    }
+
+    if cursor < end {
+        read.gaps.push((cursor, end));
+    }
+
+    // Failed pages may touch each other or the gaps between regions.
+    read.gaps.dedup_by(|next, prev| {
+        let touches = next.0 <= prev.1;
+        if touches {
+            prev.1 = prev.1.max(next.1);
+        }
+        touches
+    });
+
+    Ok(read)
+}
+
+/// Reads the mapped region `base..end` in one batch, splitting it into segments at the pages
+/// that failed.
+fn read_region(
+    process: &mut impl MemoryView,
+    base: Address,
+    end: Address,
+    read: &mut RangeRead,
+) -> Result<()> {
+    let mut buf = vec![0u8; (end - base) as usize];
+    let mut failed = vec![];
+
+    // The first chunk ends at a page boundary, so a failing page never takes others with it.
+    let head = (PAGE_SIZE - (base.to_umem() as usize % PAGE_SIZE)).min(buf.len());
+    let (first, rest) = buf.split_at_mut(head);
+
+    let ops = core::iter::once((base, first))
+        .chain(
+            rest.chunks_mut(PAGE_SIZE)
+                .enumerate()
+                .map(|(i, chunk)| (base + head + i * PAGE_SIZE, chunk)),
+        )
+        .map(|(addr, chunk)| CTup2(addr, chunk.into()));
+
+    let fail = &mut |CTup2(addr, data): ReadData| {
+        failed.push((addr, addr + data.len()));
+        true
+    };
+
+    process.read_iter(ops, None, Some(&mut fail.into()))?;
+
+    failed.sort_unstable_by_key(|&(addr, _)| addr);
+
+    let mut cursor = base;
+
+    for (fail_start, fail_end) in failed {
+        if cursor < fail_start {
+            let segment = (cursor - base) as usize..(fail_start - base) as usize;
+            read.segments.push((cursor, buf[segment].to_vec()));
+        }
+
+        read.gaps.push((fail_start, fail_end));
+
+        cursor = cursor.max(fail_end);
+    }
+
+    if cursor < end {
+        // Most regions read in full, which keeps their buffer instead of copying it.
+        if cursor == base {
+            read.segments.push((base, buf));
+        } else {
+            read.segments
+                .push((cursor, buf[(cursor - base) as usize..].to_vec()));
+        }
+    }
+
+    Ok(())
+}
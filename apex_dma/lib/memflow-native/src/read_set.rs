@@ -0,0 +1,95 @@
+use memflow::prelude::v1::*;
+
+/// A fixed set of ranges that is re-read as a whole, for values that are polled repeatedly.
+///
+/// The buffers are allocated once and every [`refresh`](Self::refresh) reads all ranges in a
+/// single batch, so polling does not have to rebuild the read list each time.
+pub struct ReadSet {
+    targets: Vec<(Address, usize)>,
+    offsets: Vec<usize>,
+    failed: Vec<bool>,
+    buf: Vec<u8>,
+}
+
+impl ReadSet {
+    /// Creates a set reading the given `(address, length)` targets.
+    ///
+    /// Nothing is read until the first [`refresh`](Self::refresh).
+    pub fn new(targets: &[(Address, usize)]) -> Self {
+        let offsets = targets
+            .iter()
+            .scan(0, |offset, &(_, len)| {
+                let start = *offset;
+                *offset += len;
+                Some(start)
+            })
+            .collect::<Vec<_>>();
+
+        let size = targets.iter().map(|&(_, len)| len).sum();
+
+        Self {
+            targets: targets.to_vec(),
+            offsets,
+            failed: vec![true; targets.len()],
+            buf: vec![0; size],
+        }
+    }
+
+    /// Re-reads all targets from `mem` in a single batch.
+    ///
+    /// Targets that can not be read in full are marked as failed until the next refresh.
+    pub fn refresh(&mut self, mem: &mut impl MemoryView) -> Result<()> {
+        self.failed.iter_mut().for_each(|failed| *failed = false);
+
+        let Self {
+            targets,
+            offsets,
+            failed,
+            buf,
+        } = self;
+
+        // The meta address is the offset in `buf`, which identifies the target on failure.
+        let mut rest = &mut buf[..];
+
+        let iter = targets
+            .iter()
+            .zip(offsets.iter())
+            .map(|(&(addr, len), &offset)| {
+                let (head, tail) = core::mem::take(&mut rest).split_at_mut(len);
+                rest = tail;
+                CTup3(addr, Address::from(offset as umem), head.into())
+            });
+
+        let callback = &mut |CTup2(meta, _): ReadData| {
+            let offset = meta.to_umem() as usize;
+            failed[offsets.partition_point(|&start| start <= offset) - 1] = true;
+            true
+        };
+
+        MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+            mem.read_raw_iter(data)
+        })
+    }
+
+    /// Number of targets in the set.
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Retrieves the bytes of the target at `index` from the last refresh.
+    ///
+    /// Returns `None` if the target could not be read.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        if *self.failed.get(index)? {
+            return None;
+        }
+
+        let offset = self.offsets[index];
+
+        Some(&self.buf[offset..offset + self.targets[index].1])
+    }
+}
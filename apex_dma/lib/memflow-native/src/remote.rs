@@ -0,0 +1,142 @@
+use memflow::prelude::v1::*;
+
+use core::marker::PhantomData;
+use core::mem::size_of;
+
+/// A pointer to a `T` in the address space of the target, stored with the target's pointer width.
+///
+/// A `Pod` struct has fixed field sizes, so a `usize` or `u64` pointer field only matches the
+/// remote layout of 64-bit targets. Structs read as a whole should declare pointer fields as
+/// memflow's [`Pointer32`] or [`Pointer64`], with one struct per target width. `RemotePtr` is for
+/// walking structures field by field when the width is only known at runtime, as with Wow64
+/// targets of a 64-bit host. The width is taken from the view's metadata.
+pub struct RemotePtr<T: ?Sized = ()> {
+    pub address: Address,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: ?Sized> RemotePtr<T> {
+    pub const NULL: Self = Self::new(Address::NULL);
+
+    pub const fn new(address: Address) -> Self {
+        Self {
+            address,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.address.is_null()
+    }
+
+    /// Size of a pointer in `mem`, in bytes.
+    pub fn width(mem: &(impl MemoryView + ?Sized)) -> usize {
+        if mem.metadata().arch_bits == 32 {
+            4
+        } else {
+            8
+        }
+    }
+
+    /// Reads the pointer stored at `addr`, in the byte order from the view's metadata.
+    pub fn read_at(mem: &mut (impl MemoryView + ?Sized), addr: Address) -> Result<Self> {
+        let width = Self::width(mem);
+        let little_endian = mem.metadata().little_endian;
+
+        let mut ptr = [0u8; 8];
+        mem.read_raw_into(addr, &mut ptr[..width])?;
+
+        Ok(Self::new(Address::from(uint_from_bytes(
+            &ptr[..width],
+            little_endian,
+        ))))
+    }
+
+    /// Reads the `index`-th pointer of the pointer array at `addr`.
+    pub fn read_index(
+        mem: &mut (impl MemoryView + ?Sized),
+        addr: Address,
+        index: usize,
+    ) -> Result<Self> {
+        Self::read_at(mem, addr + index * Self::width(mem))
+    }
+
+    /// Casts the pointer to point at a `U`.
+    pub fn cast<U: ?Sized>(self) -> RemotePtr<U> {
+        RemotePtr::new(self.address)
+    }
+}
+
+impl<T: Pod> RemotePtr<T> {
+    /// Reads the value the pointer points at.
+    ///
+    /// The value is copied as it is stored, which only matches the host if the target has the
+    /// same byte order.
+    pub fn read(&self, mem: &mut (impl MemoryView + ?Sized)) -> Result<T> {
+        if self.is_null() {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::NotFound));
+        }
+
+        let mut value = T::zeroed();
+        mem.read_raw_into(self.address, value.as_bytes_mut())?;
+
+        Ok(value)
+    }
+
+    /// Address of the `index`-th `T` of the array starting at the pointer.
+    pub fn offset(&self, index: usize) -> Address {
+        self.address + index * size_of::<T>()
+    }
+}
+
+impl<T: ?Sized> Clone for RemotePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for RemotePtr<T> {}
+
+impl<T: ?Sized> PartialEq for RemotePtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T: ?Sized> Eq for RemotePtr<T> {}
+
+impl<T: ?Sized> core::fmt::Debug for RemotePtr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "RemotePtr({:x})", self.address)
+    }
+}
+
+impl<T: ?Sized> From<Address> for RemotePtr<T> {
+    fn from(address: Address) -> Self {
+        Self::new(address)
+    }
+}
+
+/// Decodes an unsigned integer of up to 8 bytes stored in the given byte order.
+pub(crate) fn uint_from_bytes(raw: &[u8], little_endian: bool) -> u64 {
+    let mut out = [0u8; 8];
+
+    if little_endian {
+        out[..raw.len()].copy_from_slice(raw);
+        u64::from_le_bytes(out)
+    } else {
+        out[8 - raw.len()..].copy_from_slice(raw);
+        u64::from_be_bytes(out)
+    }
+}
+
+/// Encodes the low `out.len()` bytes of `value`, up to 8, in the given byte order.
+pub(crate) fn uint_to_bytes(value: u64, out: &mut [u8], little_endian: bool) {
+    let len = out.len();
+
+    if little_endian {
+        out.copy_from_slice(&value.to_le_bytes()[..len]);
+    } else {
+        out.copy_from_slice(&value.to_be_bytes()[8 - len..]);
+    }
+}
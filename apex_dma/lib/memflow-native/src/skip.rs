@@ -0,0 +1,44 @@
+use memflow::prelude::v1::*;
+
+/// Why a process was left out of a process list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A system process left out by the `skip_system` option.
+    System,
+    /// The process exited between listing and querying it.
+    Exited,
+    /// Querying the process failed, along with the OS error code behind it if there is one.
+    Failed(Error, Option<i32>),
+}
+
+/// The processes left out of the last enumeration, if recording them is enabled.
+#[cfg(not(target_os = "macos"))]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SkipLog {
+    entries: Option<Vec<(Pid, SkipReason)>>,
+}
+
+#[cfg(not(target_os = "macos"))]
+impl SkipLog {
+    /// Starts or stops recording, dropping what was recorded so far.
+    pub fn set_enabled(&mut self, enable: bool) {
+        self.entries = enable.then(Vec::new);
+    }
+
+    /// Drops what was recorded, as at the start of an enumeration.
+    pub fn clear(&mut self) {
+        if let Some(entries) = &mut self.entries {
+            entries.clear();
+        }
+    }
+
+    pub fn record(&mut self, pid: Pid, reason: SkipReason) {
+        if let Some(entries) = &mut self.entries {
+            entries.push((pid, reason));
+        }
+    }
+
+    pub fn entries(&self) -> &[(Pid, SkipReason)] {
+        self.entries.as_deref().unwrap_or_default()
+    }
+}
@@ -0,0 +1,201 @@
+use memflow::prelude::v1::*;
+
+use core::mem::size_of;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Largest single read issued by [`RegionSnapshot::capture_mapped`].
+const CAPTURE_CHUNK_SIZE: usize = 16 << 20;
+
+/// Number of offsets [`RegionSnapshot::find_all_in`] searches between checks for cancellation.
+const SCAN_CHUNK_SIZE: usize = 1 << 20;
+
+/// A copy of a contiguous range of memory.
+///
+/// Scans can slice candidate values out of the snapshot by their address, or search it for
+/// patterns, instead of reading each of them from the target again.
+pub struct RegionSnapshot {
+    base: Address,
+    buf: Vec<u8>,
+}
+
+impl RegionSnapshot {
+    /// Reads `len` bytes starting at `base`.
+    ///
+    /// Parts of the range that can not be read are left zeroed, like with any partial read.
+    pub fn capture(mem: &mut impl MemoryView, base: Address, len: usize) -> Result<Self> {
+        let mut buf = vec![0u8; len];
+        mem.read_raw_into(base, &mut buf).data_part()?;
+
+        Ok(Self { base, buf })
+    }
+
+    /// Captures every mapped region within `start..end`, reading each region in a single batch.
+    ///
+    /// Adjacent mappings with the same protection form one snapshot. Regions are read in chunks,
+    /// so large ones stay below the maximum read size. Parts that can not be read are left zeroed.
+    ///
+    /// `cancel` is checked before each region, once it is set the capture fails with
+    /// `PartialData`.
+    pub fn capture_mapped(
+        process: &mut (impl Process + MemoryView),
+        start: Address,
+        end: Address,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Vec<Self>> {
+        let mut snapshots = process
+            .mapped_mem_range_vec(0, start, end)
+            .into_iter()
+            .map(|CTup3(base, size, _)| Self {
+                base,
+                buf: vec![0u8; size as usize],
+            })
+            .collect::<Vec<_>>();
+
+        for snapshot in &mut snapshots {
+            check_cancel(cancel)?;
+
+            let base = snapshot.base;
+
+            let mut ops = snapshot
+                .buf
+                .chunks_mut(CAPTURE_CHUNK_SIZE)
+                .enumerate()
+                .map(|(i, chunk)| CTup2(base + i * CAPTURE_CHUNK_SIZE, chunk.into()))
+                .collect::<Vec<_>>();
+
+            process.read_raw_list(&mut ops).data_part()?;
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Finds all occurrences of `pattern` in `snapshots`, where `None` matches any byte.
+    ///
+    /// `cancel` is checked between chunks of each snapshot, once it is set the search fails with
+    /// `PartialData`.
+    pub fn find_all_in(
+        snapshots: &[Self],
+        pattern: &[Option<u8>],
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Vec<Address>> {
+        let mut found = vec![];
+
+        for snapshot in snapshots {
+            for start in (0..snapshot.buf.len()).step_by(SCAN_CHUNK_SIZE) {
+                check_cancel(cancel)?;
+
+                found.extend(snapshot.matches(pattern, start..start + SCAN_CHUNK_SIZE));
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Address the snapshot starts at.
+    pub fn base(&self) -> Address {
+        self.base
+    }
+
+    /// Number of captured bytes.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// All captured bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Returns whether `addr` lies within the snapshot.
+    pub fn contains(&self, addr: Address) -> bool {
+        addr >= self.base && ((addr - self.base) as usize) < self.buf.len()
+    }
+
+    /// Borrows the `len` captured bytes at `addr`.
+    ///
+    /// Returns `None` if the range is not fully within the snapshot.
+    pub fn slice(&self, addr: Address, len: usize) -> Option<&[u8]> {
+        if addr < self.base {
+            return None;
+        }
+
+        let start = usize::try_from(addr - self.base).ok()?;
+        self.buf.get(start..start.checked_add(len)?)
+    }
+
+    /// Copies the value at `addr` out of the snapshot, regardless of its alignment.
+    ///
+    /// Returns `None` if the value is not fully within the snapshot.
+    pub fn read<T: Pod>(&self, addr: Address) -> Option<T> {
+        let bytes = self.slice(addr, size_of::<T>())?;
+
+        let mut value = T::zeroed();
+        value.as_bytes_mut().copy_from_slice(bytes);
+
+        Some(value)
+    }
+
+    /// Finds the first occurrence of `pattern`, where `None` matches any byte.
+    pub fn find_pattern(&self, pattern: &[Option<u8>]) -> Option<Address> {
+        self.matches(pattern, 0..self.buf.len()).next()
+    }
+
+    /// Finds all, possibly overlapping, occurrences of `pattern`, where `None` matches any byte.
+    pub fn find_all(&self, pattern: &[Option<u8>]) -> Vec<Address> {
+        self.matches(pattern, 0..self.buf.len()).collect()
+    }
+
+    /// Finds the occurrences of `pattern` starting at the offsets in `offsets`.
+    fn matches<'a>(
+        &'a self,
+        pattern: &'a [Option<u8>],
+        offsets: Range<usize>,
+    ) -> impl Iterator<Item = Address> + 'a {
+        // An empty pattern would match everywhere, which is never what a scan wants.
+        let windows = if pattern.is_empty() {
+            [].windows(1)
+        } else {
+            self.buf.windows(pattern.len())
+        };
+
+        windows
+            .enumerate()
+            .skip(offsets.start)
+            .take(offsets.len())
+            .filter(move |(_, window)| {
+                window
+                    .iter()
+                    .zip(pattern)
+                    .all(|(byte, expected)| expected.is_none() || *expected == Some(*byte))
+            })
+            .map(move |(offset, _)| self.base + offset)
+    }
+
+    /// Iterates over the addresses from the start of the snapshot that are a multiple of
+    /// `align` apart and hold a whole `T`, together with the value.
+    pub fn values<T: Pod>(&self, align: usize) -> impl Iterator<Item = (Address, T)> + '_ {
+        let count = (self.buf.len() + align)
+            .saturating_sub(size_of::<T>())
+            .checked_div(align)
+            .unwrap_or(0);
+
+        (0..count).filter_map(move |i| {
+            let addr = self.base + i * align;
+            Some((addr, self.read(addr)?))
+        })
+    }
+}
+
+/// Fails with `PartialData` once `cancel` is set.
+fn check_cancel(cancel: Option<&AtomicBool>) -> Result<()> {
+    if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+        return Err(Error(ErrorOrigin::Other, ErrorKind::PartialData));
+    }
+
+    Ok(())
+}
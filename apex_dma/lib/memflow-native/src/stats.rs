@@ -0,0 +1,153 @@
+use memflow::prelude::v1::*;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Cumulative counters of the memory accesses of a process.
+///
+/// Only accesses that reach the target are counted, reads served by the page cache are not.
+/// Clones of a process share their counters.
+#[derive(Default)]
+pub struct Stats {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    reads: AtomicU64,
+    writes: AtomicU64,
+    failed: AtomicU64,
+    syscalls: AtomicU64,
+    // Only touched when a call fails, so the lock stays off the hot path.
+    errors: Mutex<HashMap<ErrorKind, u64>>,
+}
+
+/// The values of [`Stats`] at one point in time.
+#[derive(Clone, Debug, Default)]
+pub struct StatsSnapshot {
+    /// Bytes of successful reads.
+    pub bytes_read: u64,
+    /// Bytes of successful writes.
+    pub bytes_written: u64,
+    /// Number of successful reads.
+    pub reads: u64,
+    /// Number of successful writes.
+    pub writes: u64,
+    /// Number of reads and writes that were passed to the failure callback.
+    pub failed: u64,
+    /// Number of system calls issued to access the target.
+    pub syscalls: u64,
+    /// Number of failed calls by the kind of the returned error.
+    pub errors: HashMap<ErrorKind, u64>,
+}
+
+impl Stats {
+    /// Copies the current values of all counters.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            syscalls: self.syscalls.load(Ordering::Relaxed),
+            errors: self.errors.lock().unwrap().clone(),
+        }
+    }
+
+    /// Sets all counters back to zero.
+    pub fn reset(&self) {
+        self.bytes_read.store(0, Ordering::Relaxed);
+        self.bytes_written.store(0, Ordering::Relaxed);
+        self.reads.store(0, Ordering::Relaxed);
+        self.writes.store(0, Ordering::Relaxed);
+        self.failed.store(0, Ordering::Relaxed);
+        self.syscalls.store(0, Ordering::Relaxed);
+        self.errors.lock().unwrap().clear();
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+impl Stats {
+    pub(crate) fn add_syscalls(&self, count: u64) {
+        self.syscalls.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_read(&self, len: usize) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_write(&self, len: usize) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Counts the error of a failed call, passing the result through.
+    pub(crate) fn check<T>(&self, result: Result<T>) -> Result<T> {
+        if let Err(Error(_, kind)) = &result {
+            *self.errors.lock().unwrap().entry(*kind).or_default() += 1;
+        }
+
+        result
+    }
+
+    /// Passes the reads on to `read`, counting the successful and failed ones.
+    pub(crate) fn counted_read(
+        &self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: ReadRawMemOps,
+        read: impl FnOnce(ReadRawMemOps) -> Result<()>,
+    ) -> Result<()> {
+        // Both callbacks are wrapped, as they have to share a lifetime.
+        let ok = &mut |data| {
+            self.add_read(data_len(&data));
+            opt_call(out.as_deref_mut(), data)
+        };
+        let fail = &mut |data| {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            opt_call(out_fail.as_deref_mut(), data)
+        };
+
+        self.check(MemOps::with_raw(
+            inp,
+            Some(&mut ok.into()),
+            Some(&mut fail.into()),
+            read,
+        ))
+    }
+
+    /// Passes the writes on to `write`, counting the successful and failed ones.
+    pub(crate) fn counted_write(
+        &self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: WriteRawMemOps,
+        write: impl FnOnce(WriteRawMemOps) -> Result<()>,
+    ) -> Result<()> {
+        let ok = &mut |data| {
+            self.add_write(data_len(&data));
+            opt_call(out.as_deref_mut(), data)
+        };
+        let fail = &mut |data| {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+            opt_call(out_fail.as_deref_mut(), data)
+        };
+
+        self.check(MemOps::with_raw(
+            inp,
+            Some(&mut ok.into()),
+            Some(&mut fail.into()),
+            write,
+        ))
+    }
+}
+
+// Annotating the callbacks with the data type would give them their own lifetimes.
+#[cfg(not(target_os = "macos"))]
+fn data_len<T: core::ops::Deref<Target = [u8]>>(CTup2(_, data): &CTup2<Address, T>) -> usize {
+    data.len()
+}
@@ -0,0 +1,28 @@
+use memflow::prelude::v1::*;
+
+use std::collections::{HashSet, VecDeque};
+
+/// Collects the pids below `root` from a list of `(pid, parent pid)` pairs.
+///
+/// Only direct children are returned unless `recursive` is set, in which case the whole subtree
+/// is walked breadth-first. Each pid is visited once, so bogus cycles in the parent links
+/// (such as the idle process being its own parent) can not loop forever.
+pub(crate) fn child_pids(parents: &[(Pid, Pid)], root: Pid, recursive: bool) -> Vec<Pid> {
+    let mut visited = HashSet::from([root]);
+    let mut queue = VecDeque::from([root]);
+    let mut out = vec![];
+
+    while let Some(ppid) = queue.pop_front() {
+        for &(pid, _) in parents.iter().filter(|&&(_, p)| p == ppid) {
+            if visited.insert(pid) {
+                out.push(pid);
+
+                if recursive {
+                    queue.push_back(pid);
+                }
+            }
+        }
+    }
+
+    out
+}
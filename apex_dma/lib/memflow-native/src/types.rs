@@ -0,0 +1,236 @@
+use memflow::prelude::v1::*;
+
+/// Memory usage of a process.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    /// Size of the resident set (working set on Windows) in bytes.
+    pub resident: umem,
+    /// Size of the virtual address space the process reserved, in bytes.
+    pub virtual_size: umem,
+}
+
+/// Kernel and GUI object counts of a process.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceCounts {
+    /// Number of open kernel object handles.
+    pub handles: u32,
+    /// Number of GDI objects.
+    pub gdi_objects: u32,
+    /// Number of USER objects.
+    pub user_objects: u32,
+}
+
+/// Commonly used fields of a Windows thread environment block.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TebInfo {
+    /// Address of the TEB itself.
+    pub teb: Address,
+    /// Address of the PEB of the owning process.
+    pub peb: Address,
+    pub thread_id: u32,
+    /// Upper end of the stack, where it starts growing down from.
+    pub stack_base: Address,
+    /// Lowest committed address of the stack.
+    pub stack_limit: Address,
+}
+
+/// Access rights of a PE section.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SectionFlags {
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+impl SectionFlags {
+    /// Takes the rights from the `IMAGE_SCN_MEM_*` bits of a section's `Characteristics`.
+    pub fn from_characteristics(characteristics: u32) -> Self {
+        Self {
+            readable: characteristics & 0x4000_0000 != 0,
+            writable: characteristics & 0x8000_0000 != 0,
+            executable: characteristics & 0x2000_0000 != 0,
+        }
+    }
+}
+
+/// A section of a PE module, along with its flags.
+#[derive(Clone, Debug)]
+pub struct ModuleSection {
+    pub info: SectionInfo,
+    /// `Characteristics` of the section header.
+    pub characteristics: u32,
+    pub flags: SectionFlags,
+}
+
+/// Access rights and kind of a mapped memory region.
+///
+/// [`PageType`] only carries what memflow needs, this spells out what is known about a region.
+/// Rights the source can not express are `false`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionPermissions {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    /// Accessing the region raises a one-time guard page exception. Only exists on Windows.
+    pub guard: bool,
+    /// The memory belongs to the process alone, `MEM_PRIVATE` on Windows and copy-on-write
+    /// mappings on Linux.
+    pub private: bool,
+    /// The region maps an executable image. On Linux this is any file backed mapping.
+    pub image: bool,
+}
+
+impl From<PageType> for RegionPermissions {
+    /// Page types only describe mapped pages, so they are always readable.
+    fn from(page_type: PageType) -> Self {
+        Self {
+            read: true,
+            write: page_type.contains(PageType::WRITEABLE),
+            exec: !page_type.contains(PageType::NOEXEC),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<procfs::process::MMPermissions> for RegionPermissions {
+    fn from(perms: procfs::process::MMPermissions) -> Self {
+        use procfs::process::MMPermissions;
+
+        Self {
+            read: perms.contains(MMPermissions::READ),
+            write: perms.contains(MMPermissions::WRITE),
+            exec: perms.contains(MMPermissions::EXECUTE),
+            private: perms.contains(MMPermissions::PRIVATE),
+            ..Default::default()
+        }
+    }
+}
+
+/// A mapped memory region, along with the file backing it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedRegion {
+    pub base: Address,
+    pub size: umem,
+    pub page_type: PageType,
+    /// The same rights as `page_type` and what else is known about the region.
+    pub permissions: RegionPermissions,
+    /// Path of the backing file, `None` for anonymous memory.
+    ///
+    /// On Windows this is an NT device path such as `\Device\HarddiskVolume3\...`.
+    pub path: Option<String>,
+}
+
+/// A file mapped into a process, along with every range it is mapped at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedFile {
+    /// Path of the file, in the same form as [`MappedRegion::path`].
+    pub path: String,
+    /// Start address and size of each mapping of the file.
+    pub ranges: Vec<(Address, umem)>,
+}
+
+impl MappedFile {
+    /// Groups individual mappings by path, keeping the order paths are first seen in.
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) fn group(mappings: impl Iterator<Item = (String, (Address, umem))>) -> Vec<Self> {
+        let mut files: Vec<Self> = vec![];
+
+        for (path, range) in mappings {
+            match files.iter_mut().find(|file| file.path == path) {
+                Some(file) => file.ranges.push(range),
+                None => files.push(Self {
+                    path,
+                    ranges: vec![range],
+                }),
+            }
+        }
+
+        files
+    }
+}
+
+/// A kernel object handle held by a process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HandleInfo {
+    /// Value of the handle within the process.
+    pub handle: u64,
+    /// Kernel address of the object, null without SeDebugPrivilege.
+    pub object: Address,
+    pub granted_access: u32,
+    /// Object type, such as `File`, `Section` or `Mutant`, `None` if it could not be queried.
+    pub type_name: Option<String>,
+    /// Object name, `None` for unnamed objects and names that could not be queried.
+    ///
+    /// Files and devices are named by their NT device path such as `\Device\HarddiskVolume3\...`.
+    pub name: Option<String>,
+}
+
+/// Width of the length field read by `read_sized`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LenKind {
+    U16,
+    U32,
+    U64,
+}
+
+impl LenKind {
+    /// Reads a length of this width at `addr`.
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) fn read(self, mem: &mut impl MemoryView, addr: Address) -> Result<usize> {
+        let len = match self {
+            Self::U16 => mem.read::<u16>(addr).data()? as u64,
+            Self::U32 => mem.read::<u32>(addr).data()? as u64,
+            Self::U64 => mem.read::<u64>(addr).data()?,
+        };
+
+        // Lengths beyond the address space fail the size check like any other oversized length.
+        Ok(usize::try_from(len).unwrap_or(usize::MAX))
+    }
+}
+
+/// The registers needed to walk the stack of a thread along with the top of its stack, captured
+/// while the thread was stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreadSnapshot {
+    pub thread_id: u32,
+    /// Instruction pointer.
+    pub ip: Address,
+    /// Stack pointer, the address `stack` starts at.
+    pub sp: Address,
+    /// Frame pointer, `rbp` or `ebp`.
+    pub fp: Address,
+    /// The bytes from `sp` upwards, cut short where the stack can no longer be read.
+    pub stack: Vec<u8>,
+}
+
+impl ThreadSnapshot {
+    /// Reads up to `len` bytes from `sp` upwards, stopping at the first page that can not be read.
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) fn read_stack(mem: &mut impl MemoryView, sp: Address, len: usize) -> Vec<u8> {
+        let mut stack = vec![0u8; len];
+        let mut read = 0;
+
+        while read < len {
+            let addr = sp + read;
+            let chunk = (0x1000 - (addr.to_umem() as usize & 0xfff)).min(len - read);
+
+            if mem
+                .read_raw_into(addr, &mut stack[read..read + chunk])
+                .is_err()
+            {
+                break;
+            }
+
+            read += chunk;
+        }
+
+        stack.truncate(read);
+        stack
+    }
+}
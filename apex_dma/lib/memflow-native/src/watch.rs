@@ -0,0 +1,132 @@
+use memflow::prelude::v1::*;
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Handle to a running [`watch`] or [`watch_modules`], stopping it when cancelled or dropped.
+pub struct Watch {
+    cancel: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watch {
+    /// Stops polling and waits for the watcher thread to exit.
+    pub fn cancel(self) {}
+
+    /// Returns whether the watcher thread is still polling.
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up immediately instead of after the interval.
+        self.cancel.take();
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Polls the `len` bytes at `addr` on a dedicated thread, every `interval`.
+///
+/// `callback` is invoked with the old and new bytes whenever they changed since the last
+/// successful read. Reads that fail are skipped and do not count as changes. Polling continues
+/// until the returned [`Watch`] is cancelled or dropped.
+///
+/// `mem` is moved to the thread, pass a clone of the process to keep using it.
+pub fn watch<M, F>(
+    mut mem: M,
+    addr: Address,
+    len: usize,
+    interval: Duration,
+    mut callback: F,
+) -> Watch
+where
+    M: MemoryView + Send + 'static,
+    F: FnMut(&[u8], &[u8]) + Send + 'static,
+{
+    let mut old: Option<Vec<u8>> = None;
+    let mut new = vec![0u8; len];
+
+    poll(interval, move || {
+        if mem.read_raw_into(addr, &mut new).is_ok() {
+            match &mut old {
+                Some(old) if *old != new => {
+                    callback(old, &new);
+                    old.copy_from_slice(&new);
+                }
+                Some(_) => {}
+                None => old = Some(new.clone()),
+            }
+        }
+    })
+}
+
+/// Polls the module list of `process` on a dedicated thread, every `interval`.
+///
+/// `callback` is invoked with the modules that were loaded and those that were unloaded whenever
+/// the list changed since the last successful enumeration. Modules are told apart by their base
+/// and path, so a module that was unloaded and loaded again at another base counts as both. The
+/// first enumeration only records the modules that are loaded already. Enumerations that fail are
+/// skipped. Polling continues until the returned [`Watch`] is cancelled or dropped.
+///
+/// `process` is moved to the thread, pass a clone of the process to keep using it.
+pub fn watch_modules<P, F>(mut process: P, interval: Duration, mut callback: F) -> Watch
+where
+    P: Process + Send + 'static,
+    F: FnMut(&[ModuleInfo], &[ModuleInfo]) + Send + 'static,
+{
+    let mut old: Option<Vec<ModuleInfo>> = None;
+
+    poll(interval, move || {
+        let Ok(new) = process.module_list() else {
+            return;
+        };
+
+        if let Some(old) = &old {
+            let same = |a: &ModuleInfo, b: &ModuleInfo| a.base == b.base && a.path == b.path;
+
+            let loaded = new
+                .iter()
+                .filter(|module| !old.iter().any(|m| same(m, module)))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let unloaded = old
+                .iter()
+                .filter(|module| !new.iter().any(|m| same(m, module)))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !loaded.is_empty() || !unloaded.is_empty() {
+                callback(&loaded, &unloaded);
+            }
+        }
+
+        old = Some(new);
+    })
+}
+
+/// Runs `poll` on a dedicated thread right away and then every `interval`, until the returned
+/// [`Watch`] is cancelled or dropped.
+fn poll(interval: Duration, mut poll: impl FnMut() + Send + 'static) -> Watch {
+    let (cancel, cancelled) = mpsc::channel::<()>();
+
+    let thread = thread::spawn(move || loop {
+        poll();
+
+        match cancelled.recv_timeout(interval) {
+            Err(RecvTimeoutError::Timeout) => {}
+            _ => break,
+        }
+    });
+
+    Watch {
+        cancel: Some(cancel),
+        thread: Some(thread),
+    }
+}
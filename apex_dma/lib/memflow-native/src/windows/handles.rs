@@ -0,0 +1,197 @@
+use memflow::prelude::v1::*;
+
+use windows::Wdk::Foundation::{NtQueryObject, ObjectTypeInformation, OBJECT_INFORMATION_CLASS};
+use windows::Wdk::System::SystemInformation::{NtQuerySystemInformation, SYSTEM_INFORMATION_CLASS};
+use windows::Win32::Foundation::{
+    DuplicateHandle, DUPLICATE_SAME_ACCESS, HANDLE, NTSTATUS, UNICODE_STRING,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcess, PROCESS_DUP_HANDLE};
+
+use super::{conv_err, Handle};
+use crate::HandleInfo;
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// `SystemExtendedHandleInformation`, which the bindings do not provide.
+const SYSTEM_EXTENDED_HANDLE_INFORMATION: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS(64);
+
+/// `ObjectNameInformation`, which the bindings do not provide.
+const OBJECT_NAME_INFORMATION: OBJECT_INFORMATION_CLASS = OBJECT_INFORMATION_CLASS(1);
+
+const STATUS_INFO_LENGTH_MISMATCH: NTSTATUS = NTSTATUS(0xc000_0004_u32 as i32);
+const STATUS_BUFFER_OVERFLOW: NTSTATUS = NTSTATUS(0x8000_0005_u32 as i32);
+
+/// How long the name of a file object is waited for before it is given up on.
+const NAME_QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// `SYSTEM_HANDLE_TABLE_ENTRY_INFO_EX`, which the bindings do not provide.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct HandleEntry {
+    object: usize,
+    pid: usize,
+    handle: usize,
+    granted_access: u32,
+    creator_back_trace_index: u16,
+    object_type_index: u16,
+    attributes: u32,
+    reserved: u32,
+}
+
+/// Lists the handles held by the process `pid` along with the type and name of their objects.
+///
+/// Each handle is duplicated into this process to query its object. Handles that can not be
+/// duplicated are still listed, without type and name.
+pub(super) fn open_handles(pid: Pid) -> Result<Vec<HandleInfo>> {
+    let process: Handle = unsafe { OpenProcess(PROCESS_DUP_HANDLE, false, pid) }
+        .map_err(conv_err)?
+        .into();
+
+    let entries = system_handles()?;
+
+    // Type names only depend on the type index, which saves a query per handle.
+    let mut type_names = HashMap::new();
+
+    Ok(entries
+        .iter()
+        .filter(|entry| entry.pid == pid as usize)
+        .map(|entry| {
+            let mut info = HandleInfo {
+                handle: entry.handle as u64,
+                object: Address::from(entry.object as umem),
+                granted_access: entry.granted_access,
+                type_name: None,
+                name: None,
+            };
+
+            let Some(object) = duplicate(*process, entry.handle) else {
+                return info;
+            };
+
+            info.type_name = type_names
+                .entry(entry.object_type_index)
+                .or_insert_with(|| query_string(*object, ObjectTypeInformation))
+                .clone();
+
+            info.name = if info.type_name.as_deref() == Some("File") {
+                query_name_timeout(object)
+            } else {
+                query_string(*object, OBJECT_NAME_INFORMATION)
+            };
+
+            info
+        })
+        .collect())
+}
+
+/// Retrieves the handle table of the whole system.
+fn system_handles() -> Result<Vec<HandleEntry>> {
+    // `SYSTEM_HANDLE_INFORMATION_EX` starts with the handle count and a reserved field, u64
+    // storage keeps it aligned.
+    let mut buf = vec![0u64; 0x10000];
+
+    loop {
+        let mut len = 0;
+
+        let status = unsafe {
+            NtQuerySystemInformation(
+                SYSTEM_EXTENDED_HANDLE_INFORMATION,
+                buf.as_mut_ptr().cast(),
+                (buf.len() * 8) as u32,
+                &mut len,
+            )
+        };
+
+        if status == STATUS_INFO_LENGTH_MISMATCH {
+            // Handles keep being opened in between, so leave some room for them.
+            buf.resize(buf.len().max(len as usize / 8) * 2, 0);
+            continue;
+        }
+
+        status.ok().map_err(conv_err)?;
+        break;
+    }
+
+    let count = buf[0] as usize;
+    let entries =
+        unsafe { core::slice::from_raw_parts(buf.as_ptr().add(2).cast::<HandleEntry>(), count) };
+
+    Ok(entries.to_vec())
+}
+
+/// Duplicates the handle `handle` of `process` into this process.
+fn duplicate(process: HANDLE, handle: usize) -> Option<Handle> {
+    let mut object = HANDLE::default();
+
+    unsafe {
+        DuplicateHandle(
+            process,
+            HANDLE(handle as isize),
+            GetCurrentProcess(),
+            &mut object,
+            0,
+            false,
+            DUPLICATE_SAME_ACCESS,
+        )
+    }
+    .ok()?;
+
+    Some(object.into())
+}
+
+/// Queries the name of a file object on a separate thread, giving up after
+/// [`NAME_QUERY_TIMEOUT`].
+///
+/// Querying the name of a synchronous named pipe blocks until the pending operation on the pipe
+/// completes, which may be never. A thread that does not return in time is left behind, along
+/// with the handle it queries.
+fn query_name_timeout(object: Handle) -> Option<String> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        tx.send(query_string(*object, OBJECT_NAME_INFORMATION)).ok();
+    });
+
+    rx.recv_timeout(NAME_QUERY_TIMEOUT).ok().flatten()
+}
+
+/// Queries `class` of `object`, for classes whose information starts with a `UNICODE_STRING`.
+fn query_string(object: HANDLE, class: OBJECT_INFORMATION_CLASS) -> Option<String> {
+    let mut buf = vec![0u64; 0x80];
+    let mut len = 0;
+
+    // The first call may only report the required buffer size.
+    for _ in 0..2 {
+        let status = unsafe {
+            NtQueryObject(
+                object,
+                class,
+                Some(buf.as_mut_ptr().cast()),
+                (buf.len() * 8) as u32,
+                Some(&mut len),
+            )
+        };
+
+        if status == STATUS_INFO_LENGTH_MISMATCH || status == STATUS_BUFFER_OVERFLOW {
+            buf.resize(len as usize / 8 + 1, 0);
+            continue;
+        }
+
+        status.ok().ok()?;
+
+        let string = unsafe { &*buf.as_ptr().cast::<UNICODE_STRING>() };
+
+        if string.Buffer.is_null() || string.Length == 0 {
+            return None;
+        }
+
+        let chars =
+            unsafe { core::slice::from_raw_parts(string.Buffer.0, string.Length as usize / 2) };
+
+        return Some(String::from_utf16_lossy(chars));
+    }
+
+    None
+}
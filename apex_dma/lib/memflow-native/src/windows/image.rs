@@ -0,0 +1,209 @@
+use memflow::prelude::v1::*;
+
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_READ, MEMORY_MAPPED_VIEW_ADDRESS,
+    PAGE_READONLY, SEC_IMAGE,
+};
+
+use super::{conv_err, Handle};
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::os::windows::io::AsRawHandle;
+use std::sync::Arc;
+
+/// `IMAGE_SCN_MEM_WRITE`
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// A module image file mapped into this process, serving reads of the parts of the module that
+/// are identical in the target.
+pub(super) struct ImageView {
+    view: MEMORY_MAPPED_VIEW_ADDRESS,
+    size: usize,
+    /// Base of the module in the target.
+    base: Address,
+    /// Start and end offsets of the parts that matched the target.
+    ranges: Vec<(usize, usize)>,
+}
+
+// The view is only ever read from, and unmapped on drop.
+unsafe impl Send for ImageView {}
+unsafe impl Sync for ImageView {}
+
+impl ImageView {
+    /// Maps the image file of `module` and compares its headers and read-only sections against
+    /// the module in `mem`.
+    ///
+    /// Only parts that match byte for byte are served, so sections that were relocated, hooked
+    /// or otherwise patched are always read live.
+    pub fn map(mem: &mut impl MemoryView, module: &ModuleInfo) -> Result<Self> {
+        let file = File::open(module.path.as_ref())
+            .map_err(|e| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(e))?;
+
+        // `SEC_IMAGE` lays the file out the way the loader does, so offsets into the view are
+        // relative virtual addresses.
+        let mapping = Handle(
+            unsafe {
+                CreateFileMappingW(
+                    HANDLE(file.as_raw_handle() as isize),
+                    None,
+                    PAGE_READONLY | SEC_IMAGE,
+                    0,
+                    0,
+                    None,
+                )
+            }
+            .map_err(conv_err)?,
+        );
+
+        let view = unsafe { MapViewOfFile(*mapping, FILE_MAP_READ, 0, 0, 0) };
+
+        if view.Value.is_null() {
+            return Err(conv_err(windows::core::Error::from_win32()));
+        }
+
+        // The view keeps the mapping alive on its own.
+        let mut image = Self {
+            view,
+            size: 0,
+            base: module.base,
+            ranges: vec![],
+        };
+
+        let candidates = image.read_only_parts()?;
+
+        let mut live = vec![];
+
+        for (start, end) in candidates {
+            live.resize(end - start, 0);
+
+            if mem.read_raw_into(module.base + start, &mut live).is_ok()
+                && live == image.bytes()[start..end]
+            {
+                image.ranges.push((start, end));
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Number of bytes served from the view.
+    pub fn mapped_len(&self) -> usize {
+        self.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+
+    /// Returns whether any served part overlaps the `len` bytes at `addr`.
+    pub fn overlaps(&self, addr: Address, len: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| addr < self.base + end && addr + len > self.base + start)
+    }
+
+    /// Borrows the `len` bytes at `addr` if they lie within a single served part.
+    fn get(&self, addr: Address, len: usize) -> Option<&[u8]> {
+        if addr < self.base {
+            return None;
+        }
+
+        let start = usize::try_from(addr - self.base).ok()?;
+        let end = start.checked_add(len)?;
+
+        self.ranges
+            .iter()
+            .any(|&(s, e)| start >= s && end <= e)
+            .then(|| &self.bytes()[start..end])
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.view.Value as *const u8, self.size) }
+    }
+
+    /// Retrieves the headers and the non-writable sections of the mapped image, and records the
+    /// size of the image.
+    fn read_only_parts(&mut self) -> Result<Vec<(usize, usize)>> {
+        let invalid = || Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile);
+
+        // `SEC_IMAGE` mappings are validated by the system like any loaded image, so the headers
+        // are mapped and consistent.
+        let view = self.view.Value as *const u8;
+        let header = |offset: usize, len: usize| unsafe {
+            core::slice::from_raw_parts(view.add(offset), len)
+        };
+
+        let nt = u32::from_le_bytes(header(0x3c, 4).try_into().unwrap()) as usize;
+        let file_header = header(nt, 0x18);
+
+        if &file_header[..4] != b"PE\0\0" {
+            return Err(invalid());
+        }
+
+        let section_count = u16::from_le_bytes([file_header[6], file_header[7]]) as usize;
+        let optional_size = u16::from_le_bytes([file_header[0x14], file_header[0x15]]) as usize;
+
+        // `SizeOfImage` and `SizeOfHeaders` are at the same offsets for PE32 and PE32+.
+        let optional = header(nt + 0x18, 0x40);
+        let image_size = u32::from_le_bytes(optional[0x38..0x3c].try_into().unwrap()) as usize;
+        let headers_size = u32::from_le_bytes(optional[0x3c..0x40].try_into().unwrap()) as usize;
+
+        self.size = image_size;
+
+        let sections = header(nt + 0x18 + optional_size, section_count * 0x28);
+
+        let mut parts = vec![(0, headers_size.min(image_size))];
+
+        parts.extend(sections.chunks(0x28).filter_map(|section| {
+            let field = |offset: usize| {
+                u32::from_le_bytes(section[offset..offset + 4].try_into().unwrap()) as usize
+            };
+
+            let (size, start, characteristics) = (field(0x08), field(0x0c), field(0x24) as u32);
+
+            (characteristics & SCN_MEM_WRITE == 0 && start + size <= image_size)
+                .then_some((start, start + size))
+        }));
+
+        Ok(parts)
+    }
+}
+
+impl Drop for ImageView {
+    fn drop(&mut self) {
+        unsafe { UnmapViewOfFile(self.view) }.ok();
+    }
+}
+
+/// Serves the reads that lie within one of `views` from the local mapping, passing the others on
+/// to `read`.
+pub(super) fn image_read(
+    views: &[Arc<ImageView>],
+    ops: ReadRawMemOps,
+    read: impl FnOnce(ReadRawMemOps) -> Result<()>,
+) -> Result<()> {
+    if views.is_empty() {
+        return read(ops);
+    }
+
+    let MemOps {
+        inp,
+        out,
+        mut out_fail,
+    } = ops;
+    let out = RefCell::new(out);
+
+    let iter = inp.filter_map(|CTup3(addr, meta_addr, mut buf)| {
+        let Some(bytes) = views.iter().find_map(|view| view.get(addr, buf.len())) else {
+            return Some(CTup3(addr, meta_addr, buf));
+        };
+
+        buf.copy_from_slice(bytes);
+        opt_call(out.borrow_mut().as_deref_mut(), CTup2(meta_addr, buf));
+        None
+    });
+
+    // Both callbacks are wrapped, as they have to share a lifetime.
+    let ok = &mut |data| opt_call(out.borrow_mut().as_deref_mut(), data);
+    let fail = &mut |data| opt_call(out_fail.as_deref_mut(), data);
+
+    MemOps::with_raw(iter, Some(&mut ok.into()), Some(&mut fail.into()), read)
+}
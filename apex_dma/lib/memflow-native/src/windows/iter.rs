@@ -0,0 +1,194 @@
+use memflow::os::process::*;
+use memflow::prelude::v1::*;
+
+use windows::Win32::Foundation::{ERROR_NO_MORE_FILES, STILL_ACTIVE};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows::Win32::System::Threading::{
+    GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+
+use super::process::{is_wow64, query_command_line, session_id, split_command_line};
+use super::{conv_err, path_to_name, Handle};
+use crate::skip::{SkipLog, SkipReason};
+
+use core::mem::size_of;
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+
+/// A process from a snapshot of the process list.
+///
+/// The snapshot only carries ids and the executable name. Everything else is queried when it is
+/// first asked for, which opens a query handle to the process. The handle is shared by all
+/// further queries and closed with the entry, so reading several properties opens the process
+/// only once.
+pub struct ProcessEntry {
+    pub pid: Pid,
+    pub parent_pid: Pid,
+    pub name: String,
+    pub path: String,
+    sys_arch: ArchitectureIdent,
+    handle: Option<Handle>,
+}
+
+impl ProcessEntry {
+    /// Retrieves the command line the process was started with.
+    pub fn command_line(&mut self) -> Result<String> {
+        query_command_line(**self.handle()?)
+    }
+
+    /// Retrieves the command line split into individual arguments.
+    ///
+    /// Splitting follows the quoting rules of `CommandLineToArgvW`.
+    pub fn argv(&mut self) -> Result<Vec<String>> {
+        split_command_line(&self.command_line()?)
+    }
+
+    /// Retrieves the architecture the process runs as.
+    pub fn proc_arch(&mut self) -> Result<ArchitectureIdent> {
+        Ok(if is_wow64(**self.handle()?) {
+            ArchitectureIdent::X86(32, false)
+        } else {
+            self.sys_arch
+        })
+    }
+
+    /// Retrieves the id of the Terminal Services session the process runs in.
+    pub fn session_id(&mut self) -> Result<u32> {
+        session_id(self.pid)
+    }
+
+    /// Retrieves whether the process is still running.
+    ///
+    /// Processes that can not be opened are reported as [`ProcessState::Unknown`].
+    pub fn state(&mut self) -> ProcessState {
+        let Ok(handle) = self.handle() else {
+            return ProcessState::Unknown;
+        };
+
+        let mut code = 0;
+
+        match unsafe { GetExitCodeProcess(**handle, &mut code) } {
+            Ok(_) if code == STILL_ACTIVE.0 as u32 => ProcessState::Alive,
+            Ok(_) => ProcessState::Dead(code as _),
+            Err(_) => ProcessState::Unknown,
+        }
+    }
+
+    /// Queries everything that makes up the [`ProcessInfo`] of the process.
+    pub fn info(&mut self) -> Result<ProcessInfo> {
+        Ok(ProcessInfo {
+            address: Address::from(self.pid as umem),
+            pid: self.pid,
+            state: self.state(),
+            name: self.name.as_str().into(),
+            path: self.path.as_str().into(),
+            command_line: self.command_line()?.into(),
+            sys_arch: self.sys_arch,
+            proc_arch: self.proc_arch()?,
+            // dtb is not known/used here
+            dtb1: Address::invalid(),
+            dtb2: Address::invalid(),
+        })
+    }
+
+    fn handle(&mut self) -> Result<&Handle> {
+        if self.handle.is_none() {
+            let handle =
+                unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, self.pid as _) }
+                    .map_err(conv_err)?;
+
+            self.handle = Some(Handle::from(handle));
+        }
+
+        Ok(self.handle.as_ref().unwrap())
+    }
+}
+
+/// Minimal processes the kernel creates below the System process, which have no user mode
+/// address space to read.
+const MINIMAL_PROCESSES: &[&str] = &["Registry", "Memory Compression", "Secure System"];
+
+/// Iterates over the processes of a process list snapshot.
+pub struct ProcessIter {
+    entries: std::vec::IntoIter<ProcessEntry>,
+}
+
+impl ProcessIter {
+    /// Takes a snapshot of the process list.
+    ///
+    /// With `skip_system` set, the System Idle Process, the System process and the minimal
+    /// processes below it are left out, none of which can be opened for reading.
+    pub(super) fn snapshot(
+        sys_arch: ArchitectureIdent,
+        skip_system: bool,
+        skipped: &mut SkipLog,
+    ) -> Result<Self> {
+        let snapshot =
+            Handle(unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }.map_err(conv_err)?);
+
+        // The entry is initialized up front, so a failing first call never exposes garbage.
+        let mut entry = PROCESSENTRY32W {
+            dwSize: size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+
+        let mut entries = vec![];
+        let mut ret = unsafe { Process32FirstW(*snapshot, &mut entry) };
+
+        // Only running out of entries ends the list, any other failure of the first call is an
+        // error rather than an empty process list.
+        if let Err(err) = &ret {
+            if err.code() != ERROR_NO_MORE_FILES.to_hresult() {
+                return Err(conv_err(err.clone()));
+            }
+        }
+
+        while ret.is_ok() {
+            let len = entry.szExeFile.iter().take_while(|&&c| c != 0).count();
+            let path = OsString::from_wide(&entry.szExeFile[..len])
+                .to_string_lossy()
+                .into_owned();
+
+            let name = path_to_name(&path);
+
+            let system = match (entry.th32ProcessID, entry.th32ParentProcessID) {
+                (0 | 4, _) => true,
+                (_, 4) => MINIMAL_PROCESSES.contains(&name),
+                _ => false,
+            };
+
+            if skip_system && system {
+                skipped.record(entry.th32ProcessID as _, SkipReason::System);
+            } else {
+                entries.push(ProcessEntry {
+                    pid: entry.th32ProcessID as _,
+                    parent_pid: entry.th32ParentProcessID as _,
+                    name: name.into(),
+                    path,
+                    sys_arch,
+                    handle: None,
+                });
+            }
+
+            ret = unsafe { Process32NextW(*snapshot, &mut entry) };
+        }
+
+        Ok(Self {
+            entries: entries.into_iter(),
+        })
+    }
+}
+
+impl Iterator for ProcessIter {
+    type Item = ProcessEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
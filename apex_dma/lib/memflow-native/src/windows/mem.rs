@@ -2,10 +2,15 @@ use memflow::os::process::*;
 use memflow::prelude::v1::*;
 
 use std::ffi::c_void;
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
 
 use super::{conv_err, Handle};
+use crate::guard::{host_addressable, host_checked_read, host_checked_write};
+use crate::os_error::set_last_os_error;
+use crate::stats::Stats;
 
+use windows::Win32::Foundation::ERROR_TIMEOUT;
 use windows::Win32::System::Diagnostics::Debug::{ReadProcessMemory, WriteProcessMemory};
 use windows::Win32::System::Threading::{
     OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ, PROCESS_VM_WRITE,
@@ -14,22 +19,21 @@ use windows::Win32::System::Threading::{
 #[derive(Clone)]
 pub struct ProcessVirtualMemory {
     pub(crate) handle: Arc<Handle>,
+    pub(crate) stats: Arc<Stats>,
 }
 
 impl ProcessVirtualMemory {
-    pub fn try_new(info: &ProcessInfo) -> Result<Self> {
-        let handle: Arc<Handle> = unsafe {
-            OpenProcess(
-                PROCESS_VM_READ | PROCESS_VM_WRITE | PROCESS_QUERY_INFORMATION,
-                false,
-                info.pid as _,
-            )
-        }
-        .map_err(conv_err)
-        .map(Handle::from)?
-        .into();
-
-        Ok(Self { handle })
+    /// Opens the process described by `info`, giving up after `open_timeout` if set.
+    pub fn try_new(info: &ProcessInfo, open_timeout: Option<Duration>) -> Result<Self> {
+        let handle = match open_timeout {
+            Some(timeout) => open_process_timeout(info.pid, timeout)?,
+            None => open_process(info.pid).map_err(conv_err)?,
+        };
+
+        Ok(Self {
+            handle: Arc::new(handle),
+            stats: Arc::default(),
+        })
     }
 
     #[allow(unused)]
@@ -47,6 +51,41 @@ impl ProcessVirtualMemory {
     }
 }
 
+fn open_process(pid: Pid) -> windows::core::Result<Handle> {
+    unsafe {
+        OpenProcess(
+            PROCESS_VM_READ | PROCESS_VM_WRITE | PROCESS_QUERY_INFORMATION,
+            false,
+            pid,
+        )
+    }
+    .map(Handle::from)
+}
+
+/// Opens the process `pid` on a separate thread, giving up after `timeout`.
+///
+/// Filter drivers guarding some processes can hold `OpenProcess` up for seconds. A thread that
+/// does not return in time is left behind, and closes the handle should it still get one. The
+/// timeout is reported as `ERROR_TIMEOUT` through the last OS error.
+fn open_process_timeout(pid: Pid, timeout: Duration) -> Result<Handle> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        tx.send(open_process(pid)).ok();
+    });
+
+    match rx.recv_timeout(timeout) {
+        // The error is converted here, as the last OS error is kept per thread.
+        Ok(handle) => handle.map_err(conv_err),
+        Err(_) => {
+            set_last_os_error(ERROR_TIMEOUT.to_hresult().0);
+
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
+                .log_error(format!("opening process {pid} timed out after {timeout:?}")))
+        }
+    }
+}
+
 // Helper trait for `process_rw` to be generic.
 trait RWSlice: core::ops::Deref<Target = [u8]> {
     /// Call the appropriate system call.
@@ -97,6 +136,8 @@ impl ProcessVirtualMemory {
         }: MemOps<CTup3<Address, Address, T>, CTup2<Address, T>>,
     ) -> Result<()> {
         for CTup3(addr, meta_addr, buf) in inp {
+            self.stats.add_syscalls(1);
+
             let written = unsafe {
                 T::do_rw(
                     &self.handle,
@@ -128,19 +169,58 @@ impl ProcessVirtualMemory {
     }
 }
 
+impl ProcessVirtualMemory {
+    /// Reads `buf` with a single `ReadProcessMemory` call, returning the number of bytes read.
+    pub fn read_single(&self, addr: Address, buf: &mut [u8]) -> Result<usize> {
+        let len = self.stats.check(self.read_single_uncounted(addr, buf))?;
+        self.stats.add_read(len);
+
+        Ok(len)
+    }
+
+    fn read_single_uncounted(&self, addr: Address, buf: &mut [u8]) -> Result<usize> {
+        if !host_addressable(addr, buf.len()) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds));
+        }
+
+        self.stats.add_syscalls(1);
+
+        unsafe {
+            CSliceMut::do_rw(
+                &self.handle,
+                buf.as_ptr() as _,
+                addr.to_umem() as _,
+                buf.len(),
+            )
+        }
+    }
+}
+
 impl MemoryView for ProcessVirtualMemory {
     fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
-        self.process_rw(data)
+        let stats = self.stats.clone();
+
+        stats.counted_read(data, |data| {
+            host_checked_read(data, |data| self.process_rw(data))
+        })
     }
 
     fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
-        self.process_rw(data)
+        let stats = self.stats.clone();
+
+        stats.counted_write(data, |data| {
+            host_checked_write(data, |data| self.process_rw(data))
+        })
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
         MemoryViewMetadata {
-            arch_bits: if cfg!(pointer_width = "64") { 64 } else { 32 },
-            little_endian: cfg!(target_endianess = "little"),
+            arch_bits: if cfg!(target_pointer_width = "64") {
+                64
+            } else {
+                32
+            },
+            little_endian: cfg!(target_endian = "little"),
             max_address: Address::invalid(),
             readonly: false,
             real_size: 0,
@@ -2,34 +2,75 @@ use memflow::os::process::*;
 use memflow::prelude::v1::*;
 
 use windows::core::PCSTR;
-use windows::Win32::Foundation::{CloseHandle, HANDLE};
-
-use windows::Win32::System::Diagnostics::ToolHelp::{
-    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+use windows::Wdk::System::SystemInformation::{NtQuerySystemInformation, SYSTEM_INFORMATION_CLASS};
+use windows::Wdk::System::SystemServices::RtlGetVersion;
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, BOOL, FALSE, HANDLE, HWND, LPARAM, TRUE,
 };
 
+use windows::Win32::System::SystemInformation::{
+    GetNativeSystemInfo, OSVERSIONINFOW, PROCESSOR_ARCHITECTURE_ARM64,
+    PROCESSOR_ARCHITECTURE_INTEL, SYSTEM_INFO,
+};
 use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
 
+use windows::Win32::UI::WindowsAndMessaging::{
+    EnumWindows, GetWindowTextW, GetWindowThreadProcessId, IsWindowVisible,
+};
+
 use windows::Win32::Security::{
     AdjustTokenPrivileges, LookupPrivilegeValueA, LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED,
     TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
 };
 
-use core::mem::{size_of, MaybeUninit};
+use crate::args::{bool_arg, list_arg, millis_arg, size_arg};
+use crate::guard::{DEFAULT_MAX_READ_SIZE, DEFAULT_MIN_ADDRESS};
+use crate::hooks::{AttachHooks, ProcessHook};
+use crate::os_error::{last_os_error, set_last_os_error};
+use crate::skip::{SkipLog, SkipReason};
+use crate::tree::child_pids;
+
+use core::mem::size_of;
 use core::ptr;
-use std::ffi::OsString;
-use std::os::windows::ffi::OsStringExt;
+
+use std::time::Duration;
+
+mod handles;
+
+mod image;
+
+pub mod iter;
+use iter::ProcessIter;
 
 pub mod mem;
 use mem::ProcessVirtualMemory;
 
+pub mod peb;
+use peb::ModuleSource;
+
 pub mod process;
 use process::WindowsProcess;
 
 pub mod keyboard;
 use keyboard::WindowsKeyboard;
 
-struct KernelModule {}
+/// `SystemModuleInformation`, which the bindings do not provide.
+const SYSTEM_MODULE_INFORMATION: SYSTEM_INFORMATION_CLASS = SYSTEM_INFORMATION_CLASS(11);
+
+/// `RTL_PROCESS_MODULE_INFORMATION`, which the bindings do not provide.
+#[repr(C)]
+struct ProcessModuleInfo {
+    section: usize,
+    mapped_base: usize,
+    image_base: usize,
+    image_size: u32,
+    flags: u32,
+    load_order_index: u16,
+    init_order_index: u16,
+    load_count: u16,
+    offset_to_file_name: u16,
+    full_path_name: [u8; 256],
+}
 
 pub(crate) struct Handle(HANDLE);
 
@@ -53,28 +94,89 @@ impl Drop for Handle {
     }
 }
 
-pub fn conv_err(_err: windows::core::Error) -> Error {
+pub fn conv_err(err: windows::core::Error) -> Error {
+    set_last_os_error(err.code().0);
+
     // TODO: proper error kind
     // TODO: proper origin
     Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
 }
 
-unsafe fn enable_debug_privilege() -> Result<()> {
-    let process = GetCurrentProcess();
+/// Returns the trailing component of a path.
+///
+/// `szExeFile` is usually just the base name, but some entries carry full paths, with either
+/// separator. Strings without any separator are returned verbatim.
+fn path_to_name(path: &str) -> &str {
+    path.rsplit_once(&['\\', '/'][..])
+        .map(|(_, name)| name)
+        .unwrap_or(path)
+}
+
+/// Finds the owner of the first visible top-level window whose title contains `title`.
+fn window_pid(title: &str) -> Option<Pid> {
+    struct Search<'a> {
+        title: &'a str,
+        pid: Option<Pid>,
+    }
+
+    unsafe extern "system" fn visit(hwnd: HWND, param: LPARAM) -> BOOL {
+        let search = &mut *(param.0 as *mut Search);
+
+        if !IsWindowVisible(hwnd).as_bool() {
+            return TRUE;
+        }
+
+        let mut buf = [0u16; 512];
+        let len = GetWindowTextW(hwnd, &mut buf).max(0) as usize;
+
+        if !String::from_utf16_lossy(&buf[..len]).contains(search.title) {
+            return TRUE;
+        }
+
+        let mut pid = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        search.pid = Some(pid as Pid);
+
+        FALSE
+    }
+
+    let mut search = Search { title, pid: None };
+
+    // Stopping the enumeration early is reported as a failure, so the result is not checked.
+    unsafe { EnumWindows(Some(visit), LPARAM(&mut search as *mut Search as isize)) }.ok();
+
+    search.pid
+}
+
+/// The privileges enabled on startup unless the `privileges` argument lists others.
+const DEFAULT_PRIVILEGES: &[&str] = &["SeDebugPrivilege"];
+
+/// Enables each of the privileges `names` on the token of this process.
+///
+/// Returns whether each one is enabled now, privileges the token does not hold are not. Fails if
+/// the token can not be opened or one of the names is not a privilege.
+unsafe fn enable_privileges(names: &[String]) -> Result<Vec<(String, bool)>> {
     let mut token = HANDLE(0);
 
-    OpenProcessToken(process, TOKEN_ADJUST_PRIVILEGES, &mut token).map_err(conv_err)?;
+    OpenProcessToken(GetCurrentProcess(), TOKEN_ADJUST_PRIVILEGES, &mut token).map_err(conv_err)?;
+
+    let enabled = names
+        .iter()
+        .map(|name| Ok((name.clone(), enable_privilege(token, name)?)))
+        .collect();
+
+    CloseHandle(token).ok();
+
+    enabled
+}
 
+unsafe fn enable_privilege(token: HANDLE, name: &str) -> Result<bool> {
     let mut luid = Default::default();
 
-    let mut se_debug_name = *b"SeDebugPrivilege\0";
+    let name_z = format!("{name}\0");
 
-    LookupPrivilegeValueA(
-        PCSTR(core::ptr::null_mut()),
-        PCSTR(se_debug_name.as_mut_ptr()),
-        &mut luid,
-    )
-    .map_err(conv_err)?;
+    LookupPrivilegeValueA(PCSTR::null(), PCSTR(name_z.as_ptr()), &mut luid)
+        .map_err(|err| conv_err(err).log_error(format!("unknown privilege `{name}`")))?;
 
     let new_privileges = TOKEN_PRIVILEGES {
         PrivilegeCount: 1,
@@ -92,25 +194,337 @@ unsafe fn enable_debug_privilege() -> Result<()> {
         None,
         None,
     )
-    .map_err(conv_err)
+    .map_err(conv_err)?;
+
+    // Privileges the token does not hold are left out without failing the call.
+    Ok(GetLastError().is_ok())
+}
+
+/// Options applied to every process opened through [`WindowsOs`].
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessConfig {
+    /// Reads larger than this fail instead of being attempted. Within a batch only those reads
+    /// fail, the rest are carried out.
+    pub max_read_size: usize,
+    /// Reads starting below this fail instead of being attempted, zero disables the check.
+    /// Within a batch only those reads fail, the others are carried out.
+    pub min_address: usize,
+    pub module_source: ModuleSource,
+    /// Opening a process that takes longer than this fails, `None` waits indefinitely.
+    pub open_timeout: Option<Duration>,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            max_read_size: DEFAULT_MAX_READ_SIZE,
+            min_address: DEFAULT_MIN_ADDRESS,
+            module_source: ModuleSource::default(),
+            open_timeout: None,
+        }
+    }
 }
 
+/// The OS layer for the running Windows system.
+///
+/// Kernel structures such as `EPROCESS` are out of reach from user mode, so the address of a
+/// process is its id, `Address::from(pid as umem)`. Looking a process up by either gives the same
+/// [`ProcessInfo`].
 pub struct WindowsOs {
     info: OsInfo,
     cached_processes: Vec<ProcessInfo>,
-    cached_modules: Vec<KernelModule>,
+    cached_parent_pids: Vec<Pid>,
+    config: ProcessConfig,
+    hooks: AttachHooks,
+    /// Leave the system pseudo-processes out of process enumeration.
+    skip_system: bool,
+    skipped: SkipLog,
+    privileges: Vec<(String, bool)>,
 }
 
 impl WindowsOs {
     pub fn new(args: &OsArgs) -> Result<Self> {
-        match args.extra_args.get("elevate_token") {
-            Some("off") | Some("OFF") | Some("Off") | Some("n") | Some("N") | Some("0") => {}
-            _ => {
-                unsafe { enable_debug_privilege() }?;
-            }
+        let privileges = if bool_arg(&args.extra_args, "elevate_token", true)? {
+            let names = list_arg(&args.extra_args, "privileges", DEFAULT_PRIVILEGES);
+            unsafe { enable_privileges(&names) }?
+        } else {
+            vec![]
+        };
+
+        let module_source = match args.extra_args.get("module_source") {
+            Some(value) => ModuleSource::from_arg(value).ok_or_else(|| {
+                Error(ErrorOrigin::Args, ErrorKind::ArgValidation)
+                    .log_error(format!("invalid value for `module_source`: {value}"))
+            })?,
+            None => ModuleSource::default(),
+        };
+
+        let config = ProcessConfig {
+            max_read_size: size_arg(&args.extra_args, "max_read_size", DEFAULT_MAX_READ_SIZE)?,
+            min_address: size_arg(&args.extra_args, "min_address", DEFAULT_MIN_ADDRESS)?,
+            module_source,
+            open_timeout: millis_arg(&args.extra_args, "open_timeout")?,
+        };
+
+        let mut os = Self {
+            config,
+            skip_system: bool_arg(&args.extra_args, "skip_system", false)?,
+            privileges,
+            ..Default::default()
+        };
+
+        os.info.arch = native_arch();
+
+        // Without SeDebugPrivilege the kernel base is hidden, leaving the defaults in place.
+        if let Ok(kernel) = os.kernel_image() {
+            os.info.base = kernel.base;
+            os.info.size = kernel.size;
+        }
+
+        Ok(os)
+    }
+
+    /// Retrieves the `HRESULT` behind the last error returned on this thread.
+    ///
+    /// memflow errors only carry a generic kind, this is the code the system reported.
+    pub fn last_os_error(&self) -> Option<i32> {
+        last_os_error()
+    }
+
+    /// Retrieves the privileges enabled on startup, along with whether enabling each succeeded.
+    ///
+    /// These are the ones the `privileges` argument lists, or SeDebugPrivilege without it. The
+    /// token of an unelevated process usually lacks all but a few of them, which leaves those
+    /// disabled. Empty if the `elevate_token` argument is off.
+    pub fn privileges(&self) -> &[(String, bool)] {
+        &self.privileges
+    }
+
+    /// Registers `hook` to run whenever a process was opened, replacing the previous one.
+    ///
+    /// The hook runs with the info of the process once it was opened successfully, on the thread
+    /// that opened it. Clones of this instance made afterwards share it, processes that were
+    /// opened before are not affected.
+    pub fn set_attach_hook(&mut self, hook: Option<ProcessHook>) {
+        self.hooks.on_attach = hook;
+    }
+
+    /// Registers `hook` to run whenever a process is closed, replacing the previous one.
+    ///
+    /// A process counts as closed once it and all of its clones were dropped or reattached to
+    /// another process. The hook runs on the thread that let go of the last of them.
+    pub fn set_detach_hook(&mut self, hook: Option<ProcessHook>) {
+        self.hooks.on_detach = hook;
+    }
+
+    /// Takes a snapshot of the process list.
+    ///
+    /// Unlike [`Os::process_info_list`], per-process details are only queried for the entries
+    /// that ask for them.
+    pub fn processes(&mut self) -> Result<ProcessIter> {
+        self.skipped.clear();
+
+        ProcessIter::snapshot(self.info.arch, self.skip_system, &mut self.skipped)
+    }
+
+    /// Clones the OS layer along with the process and kernel module lists it enumerated last.
+    ///
+    /// [`Clone::clone`] starts with empty lists, so every clone enumerates processes anew before
+    /// it can look them up by address. Clones made this way look processes up right away, but
+    /// the shared lists are as stale as the ones they were taken from until the next
+    /// enumeration.
+    pub fn clone_with_cache(&self) -> Self {
+        Self {
+            cached_processes: self.cached_processes.clone(),
+            cached_parent_pids: self.cached_parent_pids.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Starts or stops recording why processes are left out of process lists.
+    ///
+    /// Off by default. Each enumeration replaces what the previous one recorded, see
+    /// [`Self::skipped_processes`].
+    pub fn set_record_skipped(&mut self, enable: bool) {
+        self.skipped.set_enabled(enable);
+    }
+
+    /// Retrieves the processes the last enumeration left out, along with the reason.
+    ///
+    /// Process lists only leave out the System and Idle processes and the minimal processes
+    /// below System with the `skip_system` option. Processes are not opened while they are
+    /// listed, so those that can not be opened are still listed. Empty unless
+    /// [`Self::set_record_skipped`] enabled recording.
+    pub fn skipped_processes(&self) -> &[(Pid, SkipReason)] {
+        self.skipped.entries()
+    }
+
+    /// Identifies this OS layer for display, `OsInfo` has no room for a name.
+    pub fn backend_name(&self) -> &'static str {
+        "native-windows"
+    }
+
+    /// Version of the OS layer, which is the crate version.
+    pub fn backend_version(&self) -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// Retrieves the major and minor version and the build number of the running system.
+    ///
+    /// Unlike `GetVersionEx`, this reports the actual version regardless of the compatibility
+    /// manifest of the executable.
+    pub fn os_version(&self) -> Result<(u32, u32, u32)> {
+        let mut version = OSVERSIONINFOW {
+            dwOSVersionInfoSize: size_of::<OSVERSIONINFOW>() as u32,
+            ..Default::default()
+        };
+
+        unsafe { RtlGetVersion(&mut version) }
+            .ok()
+            .map_err(conv_err)?;
+
+        Ok((
+            version.dwMajorVersion,
+            version.dwMinorVersion,
+            version.dwBuildNumber,
+        ))
+    }
+
+    /// Retrieves the ids of all running processes, without opening or querying any of them.
+    ///
+    /// This is the least intrusive way to list processes, nothing but the process list itself is
+    /// read.
+    pub fn process_pids(&mut self) -> Result<Vec<Pid>> {
+        self.skipped.clear();
+
+        Ok(
+            ProcessIter::snapshot(self.info.arch, self.skip_system, &mut self.skipped)?
+                .map(|entry| entry.pid)
+                .collect(),
+        )
+    }
+
+    /// Retrieves all processes named `name`, compared case-insensitively.
+    ///
+    /// Unlike [`Os::process_info_by_name`], every match is returned, so processes sharing a name
+    /// can be told apart by their command line or architecture.
+    pub fn processes_by_name(&mut self, name: &str) -> Result<Vec<ProcessInfo>> {
+        self.process_address_list()?;
+
+        Ok(self
+            .cached_processes
+            .iter()
+            .filter(|p| p.name.eq_ignore_ascii_case(name))
+            .cloned()
+            .collect())
+    }
+
+    /// Retrieves the id of the session this process runs in.
+    pub fn current_session_id(&self) -> Result<u32> {
+        process::session_id(std::process::id() as Pid)
+    }
+
+    /// Opens the process owning the first visible top-level window whose title contains `title`.
+    ///
+    /// This tells processes apart that share an executable name, but only one of them shows the
+    /// window. The title is matched case-sensitively.
+    pub fn process_by_window_title(&mut self, title: &str) -> Result<WindowsProcess> {
+        let pid =
+            window_pid(title).ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound))?;
+        self.process_by_pid(pid)
+    }
+
+    /// Retrieves the direct child processes of `pid`.
+    pub fn children(&mut self, pid: Pid) -> Result<Vec<ProcessInfo>> {
+        self.process_subtree(pid, false)
+    }
+
+    /// Retrieves all processes below `pid` in the process tree.
+    ///
+    /// Launchers often spawn the actual program as a grandchild, which can be found this way.
+    pub fn descendants(&mut self, pid: Pid) -> Result<Vec<ProcessInfo>> {
+        self.process_subtree(pid, true)
+    }
+
+    fn process_subtree(&mut self, pid: Pid, recursive: bool) -> Result<Vec<ProcessInfo>> {
+        self.process_address_list()?;
+
+        let parents = self
+            .cached_processes
+            .iter()
+            .map(|p| p.pid)
+            .zip(self.cached_parent_pids.iter().copied())
+            .collect::<Vec<_>>();
+
+        Ok(child_pids(&parents, pid, recursive)
+            .into_iter()
+            .filter_map(|pid| self.cached_processes.iter().find(|p| p.pid == pid))
+            .cloned()
+            .collect())
+    }
+}
+
+impl WindowsOs {
+    /// Retrieves the kernel image, the first entry of the system module list.
+    ///
+    /// Kernel modules are not enumerated yet, this only covers the primary module. Without
+    /// SeDebugPrivilege newer systems report a null base.
+    fn kernel_image(&self) -> Result<ModuleInfo> {
+        let mut len = 0;
+
+        // The first call only reports the required buffer size.
+        unsafe {
+            NtQuerySystemInformation(SYSTEM_MODULE_INFORMATION, ptr::null_mut(), 0, &mut len)
+        };
+
+        // `RTL_PROCESS_MODULES` starts with the module count, u64 storage keeps it aligned.
+        let mut buf = vec![0u64; len as usize / 8 + 1];
+
+        unsafe {
+            NtQuerySystemInformation(
+                SYSTEM_MODULE_INFORMATION,
+                buf.as_mut_ptr().cast(),
+                len,
+                &mut len,
+            )
+        }
+        .ok()
+        .map_err(conv_err)?;
+
+        if buf[0] as u32 == 0 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound));
         }
 
-        Ok(Default::default())
+        let module = unsafe {
+            &*buf
+                .as_ptr()
+                .cast::<u8>()
+                .add(size_of::<usize>())
+                .cast::<ProcessModuleInfo>()
+        };
+
+        let path_len = module
+            .full_path_name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(module.full_path_name.len());
+
+        let path = String::from_utf8_lossy(&module.full_path_name[..path_len]);
+        let name = path
+            .get(module.offset_to_file_name as usize..)
+            .unwrap_or(&path);
+
+        let base = Address::from(module.image_base as umem);
+
+        Ok(ModuleInfo {
+            address: base,
+            parent_process: Address::INVALID,
+            base,
+            size: module.image_size as umem,
+            name: name.into(),
+            path: path.as_ref().into(),
+            arch: self.info.arch,
+        })
     }
 }
 
@@ -119,11 +533,30 @@ impl Clone for WindowsOs {
         Self {
             info: self.info.clone(),
             cached_processes: vec![],
-            cached_modules: vec![],
+            cached_parent_pids: vec![],
+            config: self.config,
+            skip_system: self.skip_system,
+            hooks: self.hooks.clone(),
+            skipped: self.skipped.clone(),
+            privileges: self.privileges.clone(),
         }
     }
 }
 
+/// Determines the architecture of the system, which differs from the one of this process when it
+/// runs under Wow64 or emulation.
+fn native_arch() -> ArchitectureIdent {
+    let mut info = SYSTEM_INFO::default();
+    unsafe { GetNativeSystemInfo(&mut info) };
+
+    match unsafe { info.Anonymous.Anonymous.wProcessorArchitecture } {
+        PROCESSOR_ARCHITECTURE_INTEL => ArchitectureIdent::X86(32, false),
+        PROCESSOR_ARCHITECTURE_ARM64 => ArchitectureIdent::AArch64(info.dwPageSize as usize),
+        // x64, and anything unknown keeps the previous default.
+        _ => ArchitectureIdent::X86(64, false),
+    }
+}
+
 impl Default for WindowsOs {
     fn default() -> Self {
         let info = OsInfo {
@@ -134,8 +567,13 @@ impl Default for WindowsOs {
 
         Self {
             info,
-            cached_modules: vec![],
             cached_processes: vec![],
+            cached_parent_pids: vec![],
+            config: ProcessConfig::default(),
+            skip_system: false,
+            hooks: AttachHooks::default(),
+            skipped: SkipLog::default(),
+            privileges: vec![],
         }
     }
 }
@@ -148,41 +586,23 @@ impl Os for WindowsOs {
     ///
     /// The callback is fully opaque. We need this style so that C FFI can work seamlessly.
     fn process_address_list_callback(&mut self, callback: AddressCallback) -> Result<()> {
-        let handle =
-            Handle(unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }.map_err(conv_err)?);
+        self.skipped.clear();
 
-        let mut maybe_entry = MaybeUninit::<PROCESSENTRY32W>::uninit();
+        let entries = ProcessIter::snapshot(self.info.arch, self.skip_system, &mut self.skipped)?;
 
-        unsafe {
-            ptr::write(
-                &mut (*maybe_entry.as_mut_ptr()).dwSize,
-                size_of::<PROCESSENTRY32W>() as u32,
-            );
-        }
+        self.cached_processes.clear();
+        self.cached_parent_pids.clear();
 
-        let ptr = maybe_entry.as_mut_ptr();
-
-        std::iter::once(unsafe { Process32FirstW(*handle, ptr) })
-            .chain(std::iter::repeat_with(|| unsafe {
-                Process32NextW(*handle, ptr)
-            }))
-            .take_while(|b| b.is_ok())
-            .map(|_| unsafe { maybe_entry.assume_init() })
-            .map(|p| {
-                let address = Address::from(p.th32ProcessID as umem);
-                let len = p.szExeFile.iter().take_while(|&&c| c != 0).count();
-
-                let path = OsString::from_wide(&p.szExeFile[..len]);
-                let path = path.to_string_lossy();
-                let path = &*path;
-                let name = path.rsplit_once('\\').map(|(_, end)| end).unwrap_or(path);
+        entries
+            .map(|entry| {
+                let address = Address::from(entry.pid as umem);
 
                 self.cached_processes.push(ProcessInfo {
                     address,
-                    pid: address.to_umem() as _,
+                    pid: entry.pid,
                     state: ProcessState::Alive,
-                    name: name.into(),
-                    path: path.into(),
+                    name: entry.name.into(),
+                    path: entry.path.into(),
                     command_line: "".into(),
                     sys_arch: self.info.arch,
                     proc_arch: self.info.arch,
@@ -190,6 +610,7 @@ impl Os for WindowsOs {
                     dtb1: Address::invalid(),
                     dtb2: Address::invalid(),
                 });
+                self.cached_parent_pids.push(entry.parent_pid);
 
                 address
             })
@@ -199,19 +620,34 @@ impl Os for WindowsOs {
     }
 
     /// Find process information by its internal address
+    ///
+    /// Addresses are process ids. Processes that were not part of the last process list, or
+    /// with no list taken yet, are looked up in a new one.
     fn process_info_by_address(&mut self, address: Address) -> Result<ProcessInfo> {
-        self.cached_processes
-            .iter()
-            .find(|p| p.address == address)
-            .cloned()
-            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
+        let find =
+            |processes: &[ProcessInfo]| processes.iter().find(|p| p.address == address).cloned();
+
+        if let Some(info) = find(&self.cached_processes) {
+            return Ok(info);
+        }
+
+        self.process_address_list()?;
+
+        find(&self.cached_processes).ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
+    }
+
+    fn process_info_by_pid(&mut self, pid: Pid) -> Result<ProcessInfo> {
+        self.process_info_by_address(Address::from(pid as umem))
     }
 
     /// Construct a process by its info, borrowing the OS
     ///
     /// It will share the underlying memory resources
     fn process_by_info(&mut self, info: ProcessInfo) -> Result<Self::ProcessType<'_>> {
-        WindowsProcess::try_new(info)
+        let mut process = WindowsProcess::try_new(info, self.config)?;
+        process.attached = self.hooks.attach(process.info());
+
+        Ok(process)
     }
 
     /// Construct a process by its info, consuming the OS
@@ -227,16 +663,9 @@ impl Os for WindowsOs {
     /// # Arguments
     /// * `callback` - where to pass each matching module to. This is an opaque callback.
     fn module_address_list_callback(&mut self, mut callback: AddressCallback) -> Result<()> {
-        /*self.cached_modules = procfs::modules()
-        .map_err(|_| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadDir))?
-        .into_iter()
-        .map(|(_, v)| v)
-        .collect();*/
-
-        (0..self.cached_modules.len())
-            .map(Address::from)
-            .take_while(|a| callback.call(*a))
-            .for_each(|_| {});
+        // Only the kernel image is known, its base doubles as the structure address.
+        let address = self.kernel_image()?.address;
+        let _ = callback.call(address);
 
         Ok(())
     }
@@ -245,43 +674,28 @@ impl Os for WindowsOs {
     ///
     /// # Arguments
     /// * `address` - address where module's information resides in
-    fn module_by_address(&mut self, _address: Address) -> Result<ModuleInfo> {
-        /*self.cached_modules
-        .iter()
-        .skip(address.to_umem() as usize)
-        .next()
-        .map(|km| ModuleInfo {
-            address,
-            size: km.size as umem,
-            base: Address::NULL,
-            name: km
-                .name
-                .split("/")
-                .last()
-                .or(Some(""))
-                .map(ReprCString::from)
-                .unwrap(),
-            arch: self.info.arch,
-            path: km.name.clone().into(),
-            parent_process: Address::INVALID,
-        })
-        .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))*/
+    fn module_by_address(&mut self, address: Address) -> Result<ModuleInfo> {
+        let image = self.kernel_image()?;
 
-        todo!()
+        if image.address == address {
+            Ok(image)
+        } else {
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
+        }
     }
 
     /// Retrieves address of the primary module structure of the process
     ///
     /// This will generally be for the initial executable that was run
     fn primary_module_address(&mut self) -> Result<Address> {
-        Ok(self.module_by_name("ntoskrnl.exe")?.address)
+        Ok(self.kernel_image()?.address)
     }
 
     /// Retrieves information for the primary module of the process
     ///
     /// This will generally be the initial executable that was run
     fn primary_module(&mut self) -> Result<ModuleInfo> {
-        self.module_by_name("ntoskrnl.exe")
+        self.kernel_image()
     }
 
     /// Retrieves a list of all imports of a given module
@@ -332,3 +746,50 @@ impl OsKeyboard for WindowsOs {
         Ok(WindowsKeyboard::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn pid_and_address_lookups_match() {
+        // A new instance has no cached process list, so the first lookup enumerates.
+        let mut os = WindowsOs::new(&Default::default()).unwrap();
+        let pid = std::process::id() as Pid;
+
+        let by_address = os.process_info_by_address(Address::from(pid)).unwrap();
+        let by_pid = os.process_info_by_pid(pid).unwrap();
+
+        assert_eq!(by_address.pid, pid);
+        assert_eq!(by_address.address, Address::from(pid));
+        assert_eq!(format!("{by_address:?}"), format!("{by_pid:?}"));
+    }
+
+    #[test]
+    fn process_started_after_listing_is_found() {
+        let mut os = WindowsOs::new(&Default::default()).unwrap();
+        os.process_info_list().unwrap();
+
+        // Waits for commands on its stdin until it is killed.
+        let mut child = Command::new("cmd")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id() as Pid;
+
+        // The pid is missing from the cached list, which is enumerated again.
+        let by_address = os.process_info_by_address(Address::from(pid));
+        let by_pid = os.process_info_by_pid(pid);
+
+        child.kill().ok();
+        child.wait().ok();
+
+        let (by_address, by_pid) = (by_address.unwrap(), by_pid.unwrap());
+
+        assert_eq!(by_address.pid, pid);
+        assert_eq!(format!("{by_address:?}"), format!("{by_pid:?}"));
+    }
+}
@@ -0,0 +1,236 @@
+use memflow::prelude::v1::*;
+
+use windows::Wdk::System::Threading::{
+    NtQueryInformationProcess, ProcessBasicInformation, ProcessWow64Information,
+};
+use windows::Win32::Foundation::HANDLE;
+
+use super::conv_err;
+
+use core::mem::size_of_val;
+
+/// Upper bound for walked list entries, so a corrupted list can not loop forever.
+const MAX_MODULES: usize = 0x4000;
+
+/// Where the modules of a process are enumerated from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModuleSource {
+    /// `K32EnumProcessModulesEx`.
+    #[default]
+    Psapi,
+    /// Walks `InLoadOrderModuleList` of the PEB loader data through memory reads.
+    ///
+    /// This needs no module query access and sees modules as soon as the loader links them.
+    Peb,
+}
+
+impl ModuleSource {
+    /// Parses the `module_source` argument value.
+    pub fn from_arg(value: &str) -> Option<Self> {
+        match value.trim() {
+            "psapi" => Some(Self::Psapi),
+            "peb" => Some(Self::Peb),
+            _ => None,
+        }
+    }
+}
+
+/// `PROCESS_BASIC_INFORMATION`, which the enabled bindings do not provide.
+#[repr(C)]
+#[derive(Default)]
+struct ProcessBasicInfo {
+    exit_status: i32,
+    peb_base_address: usize,
+    affinity_mask: usize,
+    base_priority: i32,
+    unique_process_id: usize,
+    inherited_from_unique_process_id: usize,
+}
+
+/// Offsets into the loader structures for one pointer width.
+struct LdrLayout {
+    /// `PEB::ImageBaseAddress`
+    image_base_address: usize,
+    /// `PEB::Ldr`
+    peb_ldr: usize,
+    /// `PEB_LDR_DATA::InLoadOrderModuleList`
+    load_order_list: usize,
+    /// `LDR_DATA_TABLE_ENTRY::DllBase`
+    dll_base: usize,
+    /// `LDR_DATA_TABLE_ENTRY::SizeOfImage`
+    size_of_image: usize,
+    /// `LDR_DATA_TABLE_ENTRY::FullDllName`
+    full_dll_name: usize,
+    /// `LDR_DATA_TABLE_ENTRY::BaseDllName`
+    base_dll_name: usize,
+}
+
+const LDR_32: LdrLayout = LdrLayout {
+    image_base_address: 0x08,
+    peb_ldr: 0x0c,
+    load_order_list: 0x0c,
+    dll_base: 0x18,
+    size_of_image: 0x20,
+    full_dll_name: 0x24,
+    base_dll_name: 0x2c,
+};
+
+const LDR_64: LdrLayout = LdrLayout {
+    image_base_address: 0x10,
+    peb_ldr: 0x18,
+    load_order_list: 0x10,
+    dll_base: 0x30,
+    size_of_image: 0x40,
+    full_dll_name: 0x48,
+    base_dll_name: 0x58,
+};
+
+fn layout(bits: u8) -> &'static LdrLayout {
+    if bits == 32 {
+        &LDR_32
+    } else {
+        &LDR_64
+    }
+}
+
+/// Retrieves the address of the PEB of the given pointer width.
+///
+/// A Wow64 process has a 64-bit PEB for its native modules, and a 32-bit one for the modules
+/// of the emulated process.
+///
+/// 32-bit builds only have access to 32-bit PEBs. `ProcessBasicInformation` returns the 32-bit
+/// PEB to them for Wow64 and native targets alike, so it is used for both, and the 64-bit PEB
+/// of a Wow64 target is reported as not supported rather than walked with the wrong layout.
+pub(super) fn peb_address(handle: HANDLE, bits: u8) -> Result<Address> {
+    if cfg!(target_pointer_width = "32") && bits != 32 {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported));
+    }
+
+    let peb = if bits == 32 && cfg!(target_pointer_width = "64") {
+        let mut peb = 0usize;
+
+        unsafe {
+            NtQueryInformationProcess(
+                handle,
+                ProcessWow64Information,
+                (&mut peb as *mut usize).cast(),
+                size_of_val(&peb) as u32,
+                core::ptr::null_mut(),
+            )
+        }
+        .ok()
+        .map_err(conv_err)?;
+
+        peb
+    } else {
+        let mut info = ProcessBasicInfo::default();
+
+        unsafe {
+            NtQueryInformationProcess(
+                handle,
+                ProcessBasicInformation,
+                (&mut info as *mut ProcessBasicInfo).cast(),
+                size_of_val(&info) as u32,
+                core::ptr::null_mut(),
+            )
+        }
+        .ok()
+        .map_err(conv_err)?;
+
+        info.peb_base_address
+    };
+
+    if peb == 0 {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+    }
+
+    Ok(Address::from(peb as umem))
+}
+
+/// Reads the base address of the executable from the PEB at `peb`.
+pub(super) fn image_base_address(
+    mem: &mut impl MemoryView,
+    peb: Address,
+    bits: u8,
+) -> Result<Address> {
+    read_ptr(mem, peb + layout(bits).image_base_address, bits)
+}
+
+/// Walks the load order module list of the PEB at `peb`, returning the address of every entry.
+pub(super) fn module_entries(
+    mem: &mut impl MemoryView,
+    peb: Address,
+    bits: u8,
+) -> Result<Vec<Address>> {
+    let layout = layout(bits);
+
+    let ldr = read_ptr(mem, peb + layout.peb_ldr, bits)?;
+    let head = ldr + layout.load_order_list;
+
+    let mut entries = vec![];
+    let mut entry = read_ptr(mem, head, bits)?;
+
+    // `InLoadOrderLinks` is the first member, so the links point at the entries themselves.
+    while entry != head && !entry.is_null() && entries.len() < MAX_MODULES {
+        entries.push(entry);
+        entry = read_ptr(mem, entry, bits)?;
+    }
+
+    Ok(entries)
+}
+
+/// Parses the loader entry at `entry` into a [`ModuleInfo`].
+pub(super) fn module_info(
+    mem: &mut impl MemoryView,
+    entry: Address,
+    arch: ArchitectureIdent,
+    parent_process: Address,
+) -> Result<ModuleInfo> {
+    let bits = arch.into_obj().bits();
+    let layout = layout(bits);
+
+    let base = read_ptr(mem, entry + layout.dll_base, bits)?;
+    let size = mem.read::<u32>(entry + layout.size_of_image).data()?;
+    let path = read_unicode_string(mem, entry + layout.full_dll_name, bits)?;
+    let name = read_unicode_string(mem, entry + layout.base_dll_name, bits)?;
+
+    Ok(ModuleInfo {
+        address: entry,
+        parent_process,
+        base,
+        size: size as umem,
+        name: name.into(),
+        path: path.into(),
+        arch,
+    })
+}
+
+/// Reads a pointer of the given width.
+pub(super) fn read_ptr(mem: &mut impl MemoryView, addr: Address, bits: u8) -> Result<Address> {
+    Ok(if bits == 32 {
+        Address::from(mem.read::<u32>(addr).data()?)
+    } else {
+        Address::from(mem.read::<u64>(addr).data()?)
+    })
+}
+
+/// Reads the contents of a `UNICODE_STRING` of the given pointer width.
+///
+/// Unlike [`MemoryViewExt::read_unicode_string`](crate::MemoryViewExt::read_unicode_string), the
+/// width may differ from the one of the memory view, as with the native loader list of a Wow64
+/// process.
+fn read_unicode_string(mem: &mut impl MemoryView, addr: Address, bits: u8) -> Result<String> {
+    let len = mem.read::<u16>(addr).data()? as usize;
+
+    if len == 0 {
+        return Ok(String::new());
+    }
+
+    // The buffer pointer is aligned to the pointer size.
+    let buffer = read_ptr(mem, addr + bits as usize / 8, bits)?;
+
+    let mut wide = vec![0u16; len / 2];
+    mem.read_into(buffer, &mut wide[..]).data()?;
+
+    Ok(String::from_utf16_lossy(&wide))
+}
@@ -3,37 +3,821 @@ use memflow::os::process::*;
 use memflow::prelude::v1::*;
 use memflow::types::gap_remover::GapRemover;
 
-use super::{conv_err, ProcessVirtualMemory};
+use super::image::{image_read, ImageView};
+use super::peb::{
+    image_base_address, module_entries, module_info, peb_address, read_ptr, ModuleSource,
+};
+use super::{conv_err, Handle, ProcessConfig, ProcessVirtualMemory};
+use crate::align::aligned_read;
+use crate::cache::PageCache;
+use crate::guard::{
+    check_address, check_arch_bits, check_size, checked_read, host_addressable, low_checked_read,
+    stack_addr,
+};
+use crate::hooks::Attached;
+use crate::lru::ModuleLru;
+use crate::os_error::last_os_error;
+use crate::pe::image_bits;
+use crate::{
+    HandleInfo, LenKind, MappedFile, MappedRegion, MemInfo, RegionPermissions, RemotePtr,
+    ResourceCounts, Stats, TebInfo, ThreadSnapshot,
+};
 
-use windows::Win32::Foundation::{HINSTANCE, HMODULE};
+use windows::core::PCWSTR;
+use windows::Wdk::System::Threading::{
+    NtQueryInformationProcess, NtQueryInformationThread, ProcessCommandLineInformation,
+    ProcessVmCounters, ThreadBasicInformation,
+};
+use windows::Win32::Foundation::{
+    LocalFree, BOOL, ERROR_ACCESS_DENIED, HANDLE, HINSTANCE, HLOCAL, HMODULE, STILL_ACTIVE,
+    UNICODE_STRING,
+};
+use windows::Win32::System::Diagnostics::Debug::CheckRemoteDebuggerPresent;
+#[cfg(target_arch = "x86_64")]
+use windows::Win32::System::Diagnostics::Debug::{
+    GetThreadContext, Wow64GetThreadContext, CONTEXT, CONTEXT_CONTROL_AMD64, CONTEXT_INTEGER_AMD64,
+    WOW64_CONTEXT, WOW64_CONTEXT_CONTROL, WOW64_CONTEXT_INTEGER,
+};
+use windows::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
 use windows::Win32::System::ProcessStatus::{
-    K32EnumProcessModulesEx, K32GetModuleFileNameExA, K32GetModuleInformation, LIST_MODULES_32BIT,
-    LIST_MODULES_64BIT,
+    K32EnumProcessModulesEx, K32GetMappedFileNameW, K32GetModuleFileNameExA,
+    K32GetModuleInformation, LIST_MODULES_32BIT, LIST_MODULES_64BIT, LIST_MODULES_ALL,
+};
+
+use windows::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+use windows::Win32::System::Threading::{
+    GetExitCodeProcess, GetGuiResources, GetProcessHandleCount, IsWow64Process, OpenThread,
+    ResumeThread, SuspendThread, Wow64SuspendThread, GR_GDIOBJECTS, GR_USEROBJECTS,
+    THREAD_GET_CONTEXT, THREAD_QUERY_INFORMATION, THREAD_SUSPEND_RESUME,
 };
+use windows::Win32::UI::Shell::CommandLineToArgvW;
 
 use windows::Win32::System::Memory::{
-    VirtualQueryEx, MEMORY_BASIC_INFORMATION, MEM_FREE, MEM_RESERVE, PAGE_EXECUTE,
-    PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_READONLY,
-    PAGE_READWRITE, PAGE_WRITECOPY,
+    VirtualQueryEx, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_FREE, MEM_IMAGE, MEM_MAPPED,
+    MEM_PRIVATE, MEM_RESERVE, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+    PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_PROTECTION_FLAGS, PAGE_READONLY, PAGE_READWRITE,
+    PAGE_WRITECOPY,
 };
 
+use core::ffi::c_void;
 use core::mem::{size_of, size_of_val};
+use core::ptr;
+
+use itertools::Itertools;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Distance from the 64-bit TEB of a Wow64 thread to its 32-bit TEB.
+const WOW64_TEB_OFFSET: usize = 0x2000;
+
+/// `THREAD_BASIC_INFORMATION`, which the bindings do not provide.
+#[repr(C)]
+#[derive(Default)]
+struct ThreadBasicInfo {
+    exit_status: i32,
+    teb_base_address: usize,
+    unique_process: usize,
+    unique_thread: usize,
+    affinity_mask: usize,
+    priority: i32,
+    base_priority: i32,
+}
+
+/// `VM_COUNTERS`, which the bindings do not provide.
+#[repr(C)]
+#[derive(Default)]
+struct VmCounters {
+    peak_virtual_size: usize,
+    virtual_size: usize,
+    page_fault_count: u32,
+    peak_working_set_size: usize,
+    working_set_size: usize,
+    quota_peak_paged_pool_usage: usize,
+    quota_paged_pool_usage: usize,
+    quota_peak_non_paged_pool_usage: usize,
+    quota_non_paged_pool_usage: usize,
+    pagefile_usage: usize,
+    peak_pagefile_usage: usize,
+}
+
+/// Modules of the .NET runtimes, `clr.dll` for .NET Framework 4, `mscorwks.dll` and
+/// `mscorsvr.dll` for earlier versions and `coreclr.dll` for .NET Core and later.
+const RUNTIME_MODULES: &[&str] = &["clr.dll", "coreclr.dll", "mscorwks.dll", "mscorsvr.dll"];
 
 #[derive(Clone)]
 pub struct WindowsProcess {
     virt_mem: ProcessVirtualMemory,
     info: ProcessInfo,
     cached_modules: Vec<HMODULE>,
+    cache: Option<PageCache>,
+    page_align: bool,
+    module_lru: ModuleLru,
+    /// Runs the detach hook once the last clone is dropped.
+    pub(super) attached: Option<Arc<Attached>>,
+    config: ProcessConfig,
+    image_views: Vec<Arc<ImageView>>,
 }
 
 impl WindowsProcess {
-    pub fn try_new(info: ProcessInfo) -> Result<Self> {
+    pub fn try_new(mut info: ProcessInfo, config: ProcessConfig) -> Result<Self> {
+        let virt_mem = ProcessVirtualMemory::try_new(&info, config.open_timeout)?;
+
+        // 32-bit processes on a 64-bit system run under Wow64, and their modules have to be
+        // parsed with 32-bit pointers.
+        if is_wow64(**virt_mem.handle) {
+            info.proc_arch = ArchitectureIdent::X86(32, false);
+        }
+
         Ok(Self {
-            virt_mem: ProcessVirtualMemory::try_new(&info)?,
+            virt_mem,
             info,
             cached_modules: vec![],
+            cache: None,
+            page_align: false,
+            module_lru: ModuleLru::default(),
+            attached: None,
+            config,
+            image_views: vec![],
+        })
+    }
+
+    /// Attaches to the process described by `info` in place of the current one, as after the
+    /// target was restarted.
+    ///
+    /// The process is opened with the same configuration, the page cache keeps its time to live
+    /// and the statistics keep counting. All cached pages and modules and the mapped module
+    /// images are dropped. Fails without changing anything if the new process can not be opened
+    /// or is not running.
+    ///
+    /// The attach hook the process was opened with runs for the new process, the detach hook
+    /// for the current one.
+    pub fn reattach(&mut self, info: ProcessInfo) -> Result<()> {
+        let mut process = Self::try_new(info, self.config)?;
+
+        if !matches!(process.state(), ProcessState::Alive) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound));
+        }
+
+        process.cache = self.cache.as_ref().map(|cache| PageCache::new(cache.ttl()));
+        process.page_align = self.page_align;
+        process.module_lru.set_capacity(self.module_lru.capacity());
+        process.attached = self
+            .attached
+            .as_ref()
+            .and_then(|attached| attached.hooks().attach(&process.info));
+        process.virt_mem.stats = self.virt_mem.stats.clone();
+
+        *self = process;
+
+        Ok(())
+    }
+
+    /// Maps the image file of `module` locally and serves reads of its unmodified read-only parts
+    /// from the mapping instead of the target.
+    ///
+    /// The headers and non-writable sections are compared against the target once, and only
+    /// those that match are served. Writes through this process drop the mapping again, changes
+    /// made by the target itself afterwards are not seen. Returns the number of bytes served from
+    /// the mapping.
+    pub fn map_module_image(&mut self, module: &ModuleInfo) -> Result<usize> {
+        let view = ImageView::map(&mut self.virt_mem, module)?;
+        let len = view.mapped_len();
+
+        if len > 0 {
+            self.image_views.push(Arc::new(view));
+        }
+
+        Ok(len)
+    }
+
+    /// Drops all mappings added by [`map_module_image`](Self::map_module_image).
+    pub fn unmap_module_images(&mut self) {
+        self.image_views.clear();
+    }
+
+    /// Enables a page cache for reads, serving cached pages for up to `ttl` before refetching.
+    ///
+    /// Passing `None` disables the cache again.
+    pub fn set_page_cache(&mut self, ttl: Option<Duration>) {
+        self.cache = ttl.map(PageCache::new);
+    }
+
+    /// Rounds uncached reads out to page boundaries, reading the whole pages and passing on the
+    /// requested bytes.
+    ///
+    /// Off by default, as it reads more than requested. Reads through the page cache are always
+    /// done in whole pages.
+    pub fn set_page_align(&mut self, enable: bool) {
+        self.page_align = enable;
+    }
+
+    /// Keeps the `capacity` most recently looked up modules by name, so repeated
+    /// [`Process::module_by_name`] lookups skip walking the module list.
+    ///
+    /// Each hit is revalidated by querying the module at its base, which fails once the module
+    /// was unloaded and falls back to walking the list. Zero, the default, disables the
+    /// cache.
+    pub fn set_module_lru(&mut self, capacity: usize) {
+        self.module_lru.set_capacity(capacity);
+    }
+
+    /// Drops all pages held by the page cache.
+    pub fn invalidate_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate();
+        }
+    }
+
+    /// Drops the cached pages overlapping `start..end`, forcing fresh reads of that range.
+    pub fn invalidate_range(&mut self, start: Address, end: Address) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate_range(start, end);
+        }
+    }
+
+    /// Cumulative counters of the memory accesses of the process, see [`Stats`].
+    pub fn stats(&self) -> &Stats {
+        &self.virt_mem.stats
+    }
+
+    /// Reads `count` consecutive values of `T` at `addr` in a single read.
+    ///
+    /// The size is checked against the maximum read size before anything is allocated, so a bogus
+    /// `count` fails with `OutOfBounds`.
+    pub fn read_array<T: Pod>(&mut self, addr: Address, count: usize) -> Result<Vec<T>> {
+        let len = count.saturating_mul(size_of::<T>());
+        check_size(len, self.config.max_read_size)?;
+
+        let mut out = (0..count).map(|_| T::zeroed()).collect::<Vec<_>>();
+        self.read_into(addr, &mut out[..]).data()?;
+
+        Ok(out)
+    }
+
+    /// Reads a length-prefixed buffer, such as the contents of a string or array object.
+    ///
+    /// The length is read as `len_kind` at `len_addr`, then that many bytes are read from the
+    /// pointer at `data_ptr_addr`. A corrupt length fails with `OutOfBounds` against the maximum
+    /// read size before anything is allocated.
+    pub fn read_sized(
+        &mut self,
+        len_addr: Address,
+        len_kind: LenKind,
+        data_ptr_addr: Address,
+    ) -> Result<Vec<u8>> {
+        let len = len_kind.read(self, len_addr)?;
+        check_size(len, self.config.max_read_size)?;
+
+        let data = RemotePtr::<u8>::read_at(self, data_ptr_addr)?;
+
+        let mut buf = vec![0u8; len];
+        self.read_raw_into(data.address, &mut buf).data()?;
+
+        Ok(buf)
+    }
+
+    /// Reads `buf` from `addr` with a single system call, bypassing the page cache.
+    ///
+    /// This is a micro-benchmark tool for measuring raw syscall latency against the batched
+    /// [`MemoryView`] path, regular code should use the [`MemoryView`] reads. Returns the number
+    /// of bytes read.
+    pub fn read_raw_uncached(&self, addr: Address, buf: &mut [u8]) -> Result<usize> {
+        check_size(buf.len(), self.config.max_read_size)?;
+        check_address(addr, self.config.min_address)?;
+        self.virt_mem.read_single(addr, buf)
+    }
+
+    /// Reads a single byte at the base of the primary module, bypassing the page cache.
+    ///
+    /// This is a cheap health check after attaching, before the main loop starts. Fails with
+    /// `Configuration` if the handle lacks read access, and with `UnableToReadMemory` if the
+    /// module base is not mapped.
+    pub fn probe(&mut self) -> Result<()> {
+        let base = self.primary_module()?.base;
+
+        self.read_raw_uncached(base, &mut [0u8])
+            .map_err(|err| match last_os_error() {
+                _ if err.1 != ErrorKind::Unknown => err,
+                Some(code) if code == ERROR_ACCESS_DENIED.to_hresult().0 => {
+                    Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                }
+                _ => Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory),
+            })?;
+
+        Ok(())
+    }
+
+    /// Checks that the primary module matches the architecture the process is read with.
+    ///
+    /// The pointer width of [`ProcessInfo::proc_arch`] is compared against the optional header
+    /// magic of the primary module, so a wrong detection is caught before pointers are read
+    /// with the wrong width. Fails with `InvalidArchitecture` on a mismatch.
+    pub fn validate_arch(&mut self) -> Result<()> {
+        let base = self.primary_module()?.base;
+        let image_bits = image_bits(self, base)?;
+
+        check_arch_bits(self.info.proc_arch, image_bits, base)
+    }
+
+    /// Pointer widths of the PEBs to walk for modules of `target_arch`.
+    ///
+    /// Wow64 processes have a 64-bit PEB for their native modules in addition to the 32-bit one.
+    /// 32-bit builds can only walk the 32-bit PEB.
+    fn peb_widths(&self, target_arch: Option<&ArchitectureIdent>) -> Vec<u8> {
+        let sys_bits = self.info.sys_arch.into_obj().bits();
+        let proc_bits = self.info.proc_arch.into_obj().bits();
+
+        let mut widths = vec![sys_bits];
+
+        if proc_bits != sys_bits {
+            widths.push(proc_bits);
+        }
+
+        if cfg!(target_pointer_width = "32") {
+            widths.retain(|&bits| bits == 32);
+        }
+
+        match target_arch {
+            Some(arch) => widths
+                .into_iter()
+                .filter(|&bits| bits == arch.into_obj().bits())
+                .collect(),
+            None => widths,
+        }
+    }
+
+    /// Retrieves the command line the process was started with.
+    pub fn command_line(&self) -> Result<String> {
+        query_command_line(**self.virt_mem.handle)
+    }
+
+    /// Retrieves the command line split into individual arguments.
+    ///
+    /// Splitting follows the quoting rules of `CommandLineToArgvW`.
+    pub fn argv(&self) -> Result<Vec<String>> {
+        split_command_line(&self.command_line()?)
+    }
+
+    /// Walks the memory regions overlapping `[start, end)` as reported by `VirtualQueryEx`.
+    fn regions(
+        &self,
+        start: Address,
+        end: Address,
+    ) -> impl Iterator<Item = MEMORY_BASIC_INFORMATION> + '_ {
+        let mut cur_addr = start;
+
+        core::iter::from_fn(move || {
+            // 32-bit builds would wrap around at the end of their address space and start over.
+            if cur_addr >= end || !host_addressable(cur_addr, 0) {
+                return None;
+            }
+
+            let mut region = MEMORY_BASIC_INFORMATION::default();
+
+            if unsafe {
+                VirtualQueryEx(
+                    **self.virt_mem.handle,
+                    Some(cur_addr.to_umem() as *mut _),
+                    &mut region,
+                    size_of::<MEMORY_BASIC_INFORMATION>(),
+                )
+            } == 0
+                || region.RegionSize == 0
+            {
+                return None;
+            }
+
+            cur_addr = Address::from(
+                (region.BaseAddress as umem).saturating_add(region.RegionSize as umem),
+            );
+
+            Some(region)
         })
     }
+
+    /// Retrieves the committed, private, read/write regions of the process.
+    ///
+    /// This is where dynamically allocated data lives, which makes it a much smaller scan target
+    /// than the full `mapped_mem` output.
+    pub fn heap_ranges(&self) -> Vec<MemoryRange> {
+        self.regions(Address::NULL, Address::invalid())
+            .filter(|r| {
+                r.State == MEM_COMMIT && r.Type == MEM_PRIVATE && r.Protect == PAGE_READWRITE
+            })
+            .map(|r| {
+                CTup3(
+                    Address::from(r.BaseAddress as umem),
+                    r.RegionSize as umem,
+                    PageType::empty().write(true),
+                )
+            })
+            .collect()
+    }
+
+    /// Retrieves the committed regions overlapping `start..end`, along with their backing files.
+    ///
+    /// Unlike `mapped_mem_range`, adjacent regions are never merged. The file name is only
+    /// queried for image and mapped file regions.
+    pub fn mapped_regions(&self, start: Address, end: Address) -> Result<Vec<MappedRegion>> {
+        Ok(self
+            .regions(start, end)
+            .filter(|region| region.State == MEM_COMMIT)
+            .map(|region| {
+                let region_start = Address::from(region.BaseAddress as umem);
+                let base = region_start.max(start);
+                let size = (region_start + region.RegionSize as umem).min(end) - base;
+
+                let path = if region.Type == MEM_IMAGE || region.Type == MEM_MAPPED {
+                    self.mapped_file_name(region.BaseAddress)
+                } else {
+                    None
+                };
+
+                MappedRegion {
+                    base,
+                    size: size as umem,
+                    page_type: page_type(region.Protect),
+                    permissions: region_permissions(&region),
+                    path,
+                }
+            })
+            .collect())
+    }
+
+    /// Retrieves the files mapped into the process as data, which the module list does not show.
+    ///
+    /// Every `MEM_MAPPED` view is one range, files that are mapped multiple times are listed once
+    /// with a range per view.
+    pub fn mapped_files(&self) -> Result<Vec<MappedFile>> {
+        let views = self
+            .regions(Address::NULL, Address::invalid())
+            .filter(|region| region.Type == MEM_MAPPED)
+            .map(|region| {
+                (
+                    region.AllocationBase as umem,
+                    region.BaseAddress as umem + region.RegionSize as umem,
+                )
+            })
+            // A view consists of all consecutive regions of its allocation.
+            .coalesce(|a, b| {
+                if a.0 == b.0 {
+                    Ok((a.0, b.1))
+                } else {
+                    Err((a, b))
+                }
+            });
+
+        Ok(MappedFile::group(views.filter_map(|(base, end)| {
+            let path = self.mapped_file_name(base as *const c_void)?;
+            Some((path, (Address::from(base), end - base)))
+        })))
+    }
+
+    /// Retrieves the device path of the file mapped at `addr`.
+    fn mapped_file_name(&self, addr: *const c_void) -> Option<String> {
+        let mut name = [0u16; 1024];
+
+        let len = unsafe { K32GetMappedFileNameW(**self.virt_mem.handle, addr, &mut name) };
+
+        (len != 0).then(|| String::from_utf16_lossy(&name[..len as usize]))
+    }
+
+    /// Retrieves the base address the main executable is mapped at.
+    pub fn image_base(&self) -> Result<Address> {
+        let mut module = HMODULE(0);
+        let mut needed = 0;
+
+        // The executable is always the first module in the list.
+        unsafe {
+            K32EnumProcessModulesEx(
+                **self.virt_mem.handle,
+                &mut module,
+                size_of::<HMODULE>() as _,
+                &mut needed,
+                LIST_MODULES_ALL.0,
+            )
+            .ok()
+            .map_err(conv_err)?
+        }
+
+        if needed == 0 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound));
+        }
+
+        Ok(Address::from(module.0 as umem))
+    }
+
+    /// Retrieves the base address the main executable is mapped at from the PEB.
+    ///
+    /// Unlike [`Self::image_base`] this takes no module snapshot, only a query for the PEB and a
+    /// single read. Wow64 processes report the base from their 32-bit PEB, which is the same
+    /// executable. The field lives in the memory of the process, which is free to change it.
+    pub fn peb_image_base(&mut self) -> Result<Address> {
+        let bits = self.info.proc_arch.into_obj().bits();
+        let peb = peb_address(**self.virt_mem.handle, bits)?;
+
+        image_base_address(&mut self.virt_mem, peb, bits)
+    }
+
+    /// Looks up the module `name` like [`Process::module_by_name`].
+    ///
+    /// Every lookup enumerates the modules anew, and module information is never read through
+    /// the page cache on Windows, so this is the same as the regular lookup. It exists for parity
+    /// with Linux, where module headers are read through the cache.
+    pub fn module_by_name_fresh(&mut self, name: &str) -> Result<ModuleInfo> {
+        self.module_lru.remove(name);
+        self.module_by_name(name)
+    }
+
+    /// Lists the modules whose name contains `substr`, ignoring ASCII case.
+    ///
+    /// The module list is refreshed and walked like [`Process::module_list`], but only matching
+    /// modules are collected.
+    pub fn modules_matching(&mut self, substr: &str) -> Result<Vec<ModuleInfo>> {
+        let substr = substr.to_ascii_lowercase();
+        let mut modules = vec![];
+
+        let callback = &mut |info: ModuleInfo| {
+            if info.name.to_ascii_lowercase().contains(&substr) {
+                modules.push(info);
+            }
+            true
+        };
+
+        self.module_list_callback(None, callback.into())?;
+
+        Ok(modules)
+    }
+
+    /// Resolves the export `symbol` of `module` to its address in the process.
+    ///
+    /// The name has to match exactly, mangled names included. Walking the exports stops at the
+    /// first match, and fails with `ExportNotFound` if there is none.
+    pub fn export_address(&mut self, module: &ModuleInfo, symbol: &str) -> Result<Address> {
+        let export = self.module_export_by_name(module, symbol)?;
+        Ok(module.base + export.offset)
+    }
+
+    /// Finds the module whose image contains `addr`.
+    ///
+    /// The module list is refreshed and walked like [`Process::module_list`], stopping at the
+    /// first match. Fails with `ModuleNotFound` if no module contains the address.
+    pub fn module_by_containing_address(&mut self, addr: Address) -> Result<ModuleInfo> {
+        let mut found = None;
+
+        let callback = &mut |info: ModuleInfo| {
+            if addr >= info.base && addr < info.base + info.size {
+                found = Some(info);
+                return false;
+            }
+            true
+        };
+
+        self.module_list_callback(None, callback.into())?;
+
+        found.ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    /// Returns whether the process hosts a .NET runtime, by looking for its module.
+    ///
+    /// The module list is refreshed and walked like [`Process::module_list`], stopping at the
+    /// first runtime module. Managed assemblies themselves are not listed as modules.
+    pub fn is_managed(&mut self) -> Result<bool> {
+        let mut managed = false;
+
+        let callback = &mut |info: ModuleInfo| {
+            managed = RUNTIME_MODULES
+                .iter()
+                .any(|name| info.name.eq_ignore_ascii_case(name));
+            !managed
+        };
+
+        self.module_list_callback(None, callback.into())?;
+
+        Ok(managed)
+    }
+
+    /// Retrieves the ids of all threads of the process.
+    pub fn thread_ids(&self) -> Result<Vec<u32>> {
+        let snapshot =
+            Handle(unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) }.map_err(conv_err)?);
+
+        let mut entry = THREADENTRY32 {
+            dwSize: size_of::<THREADENTRY32>() as u32,
+            ..Default::default()
+        };
+
+        let mut tids = vec![];
+        let mut next = unsafe { Thread32First(*snapshot, &mut entry) }.is_ok();
+
+        while next {
+            if entry.th32OwnerProcessID == self.info.pid {
+                tids.push(entry.th32ThreadID);
+            }
+
+            next = unsafe { Thread32Next(*snapshot, &mut entry) }.is_ok();
+        }
+
+        Ok(tids)
+    }
+
+    /// Retrieves the thread environment block of the thread `tid`.
+    ///
+    /// For Wow64 processes this is the 64-bit TEB, the 32-bit one follows it at offset 0x2000.
+    pub fn tls_base(&self, tid: u32) -> Result<Address> {
+        let thread =
+            Handle(unsafe { OpenThread(THREAD_QUERY_INFORMATION, false, tid) }.map_err(conv_err)?);
+
+        let mut info = ThreadBasicInfo::default();
+
+        unsafe {
+            NtQueryInformationThread(
+                *thread,
+                ThreadBasicInformation,
+                &mut info as *mut _ as _,
+                size_of_val(&info) as _,
+                ptr::null_mut(),
+            )
+        }
+        .ok()
+        .map_err(conv_err)?;
+
+        if info.unique_process != self.info.pid as usize {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+        }
+
+        Ok(Address::from(info.teb_base_address as umem))
+    }
+
+    /// Retrieves the TEB of the thread `tid` that the code of the process uses.
+    ///
+    /// This is the TEB `gs` points at in 64-bit processes, and the 32-bit TEB `fs` points at in
+    /// Wow64 processes.
+    pub fn teb_base(&self, tid: u32) -> Result<Address> {
+        let teb = self.tls_base(tid)?;
+
+        if is_wow64(**self.virt_mem.handle) {
+            Ok(teb + WOW64_TEB_OFFSET)
+        } else {
+            Ok(teb)
+        }
+    }
+
+    /// Reads the PEB pointer, thread id and stack range out of the TEB of the thread `tid`.
+    pub fn teb_info(&mut self, tid: u32) -> Result<TebInfo> {
+        let teb = self.teb_base(tid)?;
+        let bits = self.info.proc_arch.into_obj().bits();
+
+        // `NT_TIB` starts the TEB, `ClientId` and `ProcessEnvironmentBlock` follow it.
+        let (stack_base, stack_limit, thread_id, peb) = if bits == 32 {
+            (0x04, 0x08, 0x24, 0x30)
+        } else {
+            (0x08, 0x10, 0x48, 0x60)
+        };
+
+        Ok(TebInfo {
+            teb,
+            peb: read_ptr(&mut self.virt_mem, teb + peb, bits)?,
+            thread_id: read_ptr(&mut self.virt_mem, teb + thread_id, bits)?.to_umem() as u32,
+            stack_base: read_ptr(&mut self.virt_mem, teb + stack_base, bits)?,
+            stack_limit: read_ptr(&mut self.virt_mem, teb + stack_limit, bits)?,
+        })
+    }
+
+    /// Retrieves the stack range of the thread `tid` from its TEB, as the base it grows down
+    /// from and the lowest committed address.
+    pub fn thread_stack(&mut self, tid: u32) -> Result<(Address, Address)> {
+        let teb = self.teb_info(tid)?;
+        Ok((teb.stack_base, teb.stack_limit))
+    }
+
+    /// Reads `buf` from `offset` bytes below the stack base of the thread `tid`, see
+    /// [`Self::thread_stack`].
+    ///
+    /// Fails with `OutOfBounds` if the read would reach past the stack base.
+    pub fn read_stack(&mut self, tid: u32, offset: umem, buf: &mut [u8]) -> Result<()> {
+        let (base, _) = self.thread_stack(tid)?;
+        self.read_raw_into(stack_addr(base, offset, buf.len())?, buf)?;
+
+        Ok(())
+    }
+
+    /// Captures the registers and up to `stack_len` bytes of the stack of the thread `tid` as one
+    /// consistent snapshot.
+    ///
+    /// The thread is suspended while its registers and stack are read, bypassing the page cache,
+    /// and resumed afterwards. Other threads keep running. Fails with `NotFound` if `tid` is not
+    /// a thread of this process. Only supported by 64-bit builds.
+    pub fn thread_snapshot(&mut self, tid: u32, stack_len: usize) -> Result<ThreadSnapshot> {
+        self.tls_base(tid)?;
+
+        let thread = Handle(
+            unsafe { OpenThread(THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT, false, tid) }
+                .map_err(conv_err)?,
+        );
+
+        let wow64 = is_wow64(**self.virt_mem.handle);
+
+        let suspended = unsafe {
+            if wow64 {
+                Wow64SuspendThread(*thread)
+            } else {
+                SuspendThread(*thread)
+            }
+        };
+
+        if suspended == u32::MAX {
+            return Err(conv_err(windows::core::Error::from_win32()));
+        }
+
+        // Suspension is asynchronous, reading the context waits for it to take effect, so the
+        // stack is only read once the thread stopped.
+        let snapshot = thread_registers(*thread, wow64).map(|(ip, sp, fp)| ThreadSnapshot {
+            thread_id: tid,
+            ip,
+            sp,
+            fp,
+            stack: ThreadSnapshot::read_stack(&mut self.virt_mem, sp, stack_len),
+        });
+
+        unsafe { ResumeThread(*thread) };
+
+        snapshot
+    }
+
+    /// Checks whether a debugger is attached to the process.
+    pub fn is_debugged(&self) -> Result<bool> {
+        let mut present = BOOL(0);
+
+        unsafe { CheckRemoteDebuggerPresent(**self.virt_mem.handle, &mut present) }
+            .map_err(conv_err)?;
+
+        Ok(present.as_bool())
+    }
+
+    /// Windows does not tell which process debugs another, tracer ids only exist on Linux.
+    pub fn tracer_pid(&self) -> Result<Option<Pid>> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+    }
+
+    /// Retrieves the current memory usage of the process.
+    pub fn memory_info(&self) -> Result<MemInfo> {
+        let mut counters = VmCounters::default();
+
+        unsafe {
+            NtQueryInformationProcess(
+                **self.virt_mem.handle,
+                ProcessVmCounters,
+                (&mut counters as *mut VmCounters).cast(),
+                size_of_val(&counters) as u32,
+                ptr::null_mut(),
+            )
+        }
+        .ok()
+        .map_err(conv_err)?;
+
+        Ok(MemInfo {
+            resident: counters.working_set_size as _,
+            virtual_size: counters.virtual_size as _,
+        })
+    }
+
+    /// Lists the kernel object handles held by the process, with the type and name of each
+    /// object where they can be queried.
+    ///
+    /// Requires the right to duplicate handles of the process. Names of file objects that can
+    /// not be queried in time, as with some named pipes, are left out.
+    pub fn open_handles(&self) -> Result<Vec<HandleInfo>> {
+        super::handles::open_handles(self.info.pid)
+    }
+
+    /// Retrieves the handle and GDI/USER object counts of the process.
+    ///
+    /// Steadily growing counts usually point to a resource leak in the target.
+    pub fn resource_counts(&self) -> Result<ResourceCounts> {
+        let handle = **self.virt_mem.handle;
+
+        let mut handles = 0;
+        unsafe { GetProcessHandleCount(handle, &mut handles) }.map_err(conv_err)?;
+
+        Ok(ResourceCounts {
+            handles,
+            gdi_objects: unsafe { GetGuiResources(handle, GR_GDIOBJECTS) },
+            user_objects: unsafe { GetGuiResources(handle, GR_USEROBJECTS) },
+        })
+    }
+
+    /// Retrieves the id of the Terminal Services session the process runs in.
+    ///
+    /// Compare against [`WindowsOs::current_session_id`](super::WindowsOs::current_session_id)
+    /// to tell apart processes of other users or RDP sessions.
+    pub fn session_id(&self) -> Result<u32> {
+        session_id(self.info.pid)
+    }
 }
 
 cglue_impl_group!(WindowsProcess, ProcessInstance, {});
@@ -52,15 +836,34 @@ impl Process for WindowsProcess {
         target_arch: Option<&ArchitectureIdent>,
         mut callback: ModuleAddressCallback,
     ) -> Result<()> {
+        if self.config.module_source == ModuleSource::Peb {
+            for bits in self.peb_widths(target_arch) {
+                let peb = peb_address(**self.virt_mem.handle, bits)?;
+                let arch = ArchitectureIdent::X86(bits, false);
+
+                callback.extend(
+                    module_entries(&mut self.virt_mem, peb, bits)?
+                        .into_iter()
+                        .map(|address| ModuleAddressInfo { address, arch }),
+                );
+            }
+
+            return Ok(());
+        }
+
         let filter_flags = match target_arch {
             Some(ident) => match ident.into_obj().bits() {
                 32 => [Some(LIST_MODULES_32BIT), None],
                 64 => [Some(LIST_MODULES_64BIT), None],
-                _ => [Some(LIST_MODULES_32BIT), Some(LIST_MODULES_64BIT)],
+                _ => [Some(LIST_MODULES_64BIT), Some(LIST_MODULES_32BIT)],
             },
-            None => [Some(LIST_MODULES_32BIT), Some(LIST_MODULES_64BIT)],
+            // The 64-bit list goes first, so its entries are kept when both lists contain a module.
+            None => [Some(LIST_MODULES_64BIT), Some(LIST_MODULES_32BIT)],
         };
 
+        // A module handle is its base address, so modules listed by both views share it.
+        let mut seen = HashSet::new();
+
         for f in IntoIterator::into_iter(filter_flags).flatten() {
             self.cached_modules.clear();
             self.cached_modules.resize(1024, HMODULE(0));
@@ -99,10 +902,15 @@ impl Process for WindowsProcess {
                 _ => ArchitectureIdent::Unknown(0),
             };
 
-            callback.extend(self.cached_modules.iter().map(|&m| ModuleAddressInfo {
-                address: Address::from(m.0 as umem),
-                arch,
-            }));
+            callback.extend(
+                self.cached_modules
+                    .iter()
+                    .filter(|m| seen.insert(m.0))
+                    .map(|&m| ModuleAddressInfo {
+                        address: Address::from(m.0 as umem),
+                        arch,
+                    }),
+            );
         }
 
         Ok(())
@@ -118,6 +926,10 @@ impl Process for WindowsProcess {
         address: Address,
         arch: ArchitectureIdent,
     ) -> Result<ModuleInfo> {
+        if self.config.module_source == ModuleSource::Peb {
+            return module_info(&mut self.virt_mem, address, arch, self.info.address);
+        }
+
         let mut path = [0u8; 128];
 
         if unsafe {
@@ -160,6 +972,44 @@ impl Process for WindowsProcess {
         })
     }
 
+    /// Finds a module by its name, trying the module name cache first if no architecture is
+    /// given, see [`set_module_lru`](WindowsProcess::set_module_lru).
+    fn module_by_name_arch(
+        &mut self,
+        name: &str,
+        architecture: Option<&ArchitectureIdent>,
+    ) -> Result<ModuleInfo> {
+        if architecture.is_none() {
+            if let Some(cached) = self.module_lru.get(name) {
+                match self.module_by_address(cached.address, cached.arch) {
+                    Ok(info) if info.name.as_ref() == name => return Ok(info),
+                    _ => self.module_lru.remove(name),
+                }
+            }
+        }
+
+        let mut found = None;
+
+        let callback = &mut |info: ModuleInfo| {
+            if info.name.as_ref() == name {
+                found = Some(info);
+                return false;
+            }
+            true
+        };
+
+        self.module_list_callback(architecture, callback.into())?;
+
+        let info = found.ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))?;
+
+        // Lookups for one architecture may skip a module of the same name listed before it.
+        if architecture.is_none() {
+            self.module_lru.insert(info.clone());
+        }
+
+        Ok(info)
+    }
+
     fn module_import_list_callback(
         &mut self,
         info: &ModuleInfo,
@@ -188,8 +1038,18 @@ impl Process for WindowsProcess {
     ///
     /// This will generally be for the initial executable that was run
     fn primary_module_address(&mut self) -> Result<Address> {
-        // TODO: Is it always 0th mod?
-        Ok(Address::from(0))
+        if self.config.module_source == ModuleSource::Peb {
+            // The executable is always the first entry of the load order list.
+            let bits = self.info.proc_arch.into_obj().bits();
+            let peb = peb_address(**self.virt_mem.handle, bits)?;
+
+            return module_entries(&mut self.virt_mem, peb, bits)?
+                .first()
+                .copied()
+                .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound));
+        }
+
+        self.image_base()
     }
 
     /// Retrieves the process info
@@ -199,7 +1059,13 @@ impl Process for WindowsProcess {
 
     /// Retrieves the state of the process
     fn state(&mut self) -> ProcessState {
-        ProcessState::Unknown
+        let mut code = 0;
+
+        match unsafe { GetExitCodeProcess(**self.virt_mem.handle, &mut code) } {
+            Ok(_) if code == STILL_ACTIVE.0 as u32 => ProcessState::Alive,
+            Ok(_) => ProcessState::Dead(code as _),
+            Err(_) => ProcessState::Unknown,
+        }
     }
 
     /// Changes the dtb this process uses for memory translations.
@@ -217,44 +1083,15 @@ impl Process for WindowsProcess {
     ) {
         let mut gap_remover = GapRemover::new(out, gap_size, start, end);
 
-        let mut region = Default::default();
-
-        let mut cur_addr = start;
-
-        while unsafe {
-            VirtualQueryEx(
-                **self.virt_mem.handle,
-                Some(cur_addr.to_umem() as *mut _),
-                &mut region,
-                size_of::<MEMORY_BASIC_INFORMATION>(),
-            )
-        } > 0
-            && cur_addr < end
-        {
-            cur_addr = Address::from(
-                (region.BaseAddress as umem).saturating_add(region.RegionSize as umem),
-            );
-
+        for region in self.regions(start, end) {
             if region.State == MEM_FREE || region.State == MEM_RESERVE || region.RegionSize == 0 {
                 continue;
             }
 
-            let page_type = PageType::empty();
-
-            let page_type = match region.Protect {
-                PAGE_EXECUTE | PAGE_EXECUTE_READ => page_type.noexec(false),
-                PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY => {
-                    page_type.noexec(false).write(true)
-                }
-                PAGE_READWRITE | PAGE_WRITECOPY => page_type.write(true),
-                PAGE_READONLY => page_type.write(false),
-                _ => page_type,
-            };
-
             let range = CTup3(
                 Address::from(region.BaseAddress as umem),
                 region.RegionSize as _,
-                page_type,
+                page_type(region.Protect),
             );
 
             gap_remover.push_range(range);
@@ -264,14 +1101,219 @@ impl Process for WindowsProcess {
 
 impl MemoryView for WindowsProcess {
     fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
-        self.virt_mem.read_raw_iter(data)
+        let min_address = self.config.min_address;
+
+        checked_read(data, self.config.max_read_size, |data| {
+            low_checked_read(data, min_address, |data| {
+                image_read(&self.image_views, data, |data| match &mut self.cache {
+                    Some(cache) => cache.read(&mut self.virt_mem, data),
+                    None if self.page_align => aligned_read(&mut self.virt_mem, data),
+                    None => self.virt_mem.read_raw_iter(data),
+                })
+            })
+        })
     }
 
     fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
-        self.virt_mem.write_raw_iter(data)
+        let MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        } = data;
+
+        // Mapped images would keep serving the old contents of written ranges.
+        let views = &mut self.image_views;
+        let iter = inp.inspect(|CTup3(addr, _, buf)| {
+            views.retain(|view| !view.overlaps(*addr, buf.len()));
+        });
+
+        let ok = &mut |data| opt_call(out.as_deref_mut(), data);
+        let fail = &mut |data| opt_call(out_fail.as_deref_mut(), data);
+
+        MemOps::with_raw(
+            iter,
+            Some(&mut ok.into()),
+            Some(&mut fail.into()),
+            |data| match &mut self.cache {
+                Some(cache) => cache.write(&mut self.virt_mem, data),
+                None => self.virt_mem.write_raw_iter(data),
+            },
+        )
     }
 
     fn metadata(&self) -> MemoryViewMetadata {
-        self.virt_mem.metadata()
+        MemoryViewMetadata {
+            arch_bits: self.info.proc_arch.into_obj().bits(),
+            ..self.virt_mem.metadata()
+        }
+    }
+}
+
+/// Queries the command line of the process behind `handle`.
+pub(super) fn query_command_line(handle: HANDLE) -> Result<String> {
+    let mut len = 0;
+
+    // The first call only reports the required buffer size.
+    unsafe {
+        NtQueryInformationProcess(
+            handle,
+            ProcessCommandLineInformation,
+            ptr::null_mut(),
+            0,
+            &mut len,
+        )
+    };
+
+    if (len as usize) < size_of::<UNICODE_STRING>() {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Unknown));
+    }
+
+    // u64 storage keeps the leading UNICODE_STRING properly aligned.
+    let mut buf = vec![0u64; len as usize / 8 + 1];
+
+    unsafe {
+        NtQueryInformationProcess(
+            handle,
+            ProcessCommandLineInformation,
+            buf.as_mut_ptr().cast(),
+            len,
+            &mut len,
+        )
+    }
+    .ok()
+    .map_err(conv_err)?;
+
+    let cmdline = unsafe { &*buf.as_ptr().cast::<UNICODE_STRING>() };
+
+    if cmdline.Length == 0 || cmdline.Buffer.is_null() {
+        return Ok(String::new());
+    }
+
+    let wide =
+        unsafe { core::slice::from_raw_parts(cmdline.Buffer.0, cmdline.Length as usize / 2) };
+
+    Ok(String::from_utf16_lossy(wide))
+}
+
+/// Retrieves the session id of the process `pid`.
+pub(super) fn session_id(pid: Pid) -> Result<u32> {
+    let mut session = 0;
+    unsafe { ProcessIdToSessionId(pid, &mut session) }.map_err(conv_err)?;
+    Ok(session)
+}
+
+/// Splits a command line into individual arguments, following the quoting rules of
+/// `CommandLineToArgvW`.
+pub(super) fn split_command_line(command_line: &str) -> Result<Vec<String>> {
+    // `CommandLineToArgvW` returns our own executable path for an empty string.
+    if command_line.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let wide = command_line
+        .encode_utf16()
+        .chain(Some(0))
+        .collect::<Vec<_>>();
+
+    let mut argc = 0;
+    let argv = unsafe { CommandLineToArgvW(PCWSTR(wide.as_ptr()), &mut argc) };
+
+    if argv.is_null() {
+        return Err(conv_err(windows::core::Error::from_win32()));
+    }
+
+    let args = unsafe { core::slice::from_raw_parts(argv, argc as usize) }
+        .iter()
+        .map(|arg| String::from_utf16_lossy(unsafe { arg.as_wide() }))
+        .collect();
+
+    // LocalFree reports success as a null handle, which the bindings treat as an error.
+    let _ = unsafe { LocalFree(HLOCAL(argv.cast())) };
+
+    Ok(args)
+}
+
+/// Reads the instruction, stack and frame pointers of the suspended `thread`.
+#[cfg(target_arch = "x86_64")]
+fn thread_registers(thread: HANDLE, wow64: bool) -> Result<(Address, Address, Address)> {
+    if wow64 {
+        let mut context = WOW64_CONTEXT {
+            ContextFlags: WOW64_CONTEXT_CONTROL | WOW64_CONTEXT_INTEGER,
+            ..Default::default()
+        };
+
+        unsafe { Wow64GetThreadContext(thread, &mut context) }.map_err(conv_err)?;
+
+        return Ok((
+            Address::from(context.Eip),
+            Address::from(context.Esp),
+            Address::from(context.Ebp),
+        ));
+    }
+
+    let mut context = CONTEXT {
+        ContextFlags: CONTEXT_CONTROL_AMD64 | CONTEXT_INTEGER_AMD64,
+        ..Default::default()
+    };
+
+    unsafe { GetThreadContext(thread, &mut context) }.map_err(conv_err)?;
+
+    Ok((
+        Address::from(context.Rip),
+        Address::from(context.Rsp),
+        Address::from(context.Rbp),
+    ))
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn thread_registers(_thread: HANDLE, _wow64: bool) -> Result<(Address, Address, Address)> {
+    Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported))
+}
+
+/// Checks whether the process behind `handle` is a 32-bit process running under Wow64.
+pub(super) fn is_wow64(handle: HANDLE) -> bool {
+    let mut wow64 = BOOL(0);
+    unsafe { IsWow64Process(handle, &mut wow64) }.is_ok() && wow64.as_bool()
+}
+
+/// Translates region protection flags into a memflow page type.
+fn page_type(protect: PAGE_PROTECTION_FLAGS) -> PageType {
+    let page_type = PageType::empty();
+
+    match protect {
+        PAGE_EXECUTE | PAGE_EXECUTE_READ => page_type.noexec(false),
+        PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY => page_type.noexec(false).write(true),
+        PAGE_READWRITE | PAGE_WRITECOPY => page_type.write(true),
+        PAGE_READONLY => page_type.write(false),
+        _ => page_type,
+    }
+}
+
+/// Spells out the protection and type of a committed region.
+fn region_permissions(region: &MEMORY_BASIC_INFORMATION) -> RegionPermissions {
+    // The high bits are modifiers such as PAGE_GUARD, the low byte is the base protection.
+    let protect = PAGE_PROTECTION_FLAGS(region.Protect.0 & 0xff);
+
+    RegionPermissions {
+        read: matches!(
+            protect,
+            PAGE_READONLY
+                | PAGE_READWRITE
+                | PAGE_WRITECOPY
+                | PAGE_EXECUTE_READ
+                | PAGE_EXECUTE_READWRITE
+                | PAGE_EXECUTE_WRITECOPY
+        ),
+        write: matches!(
+            protect,
+            PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY
+        ),
+        exec: matches!(
+            protect,
+            PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY
+        ),
+        guard: region.Protect.0 & PAGE_GUARD.0 != 0,
+        private: region.Type == MEM_PRIVATE,
+        image: region.Type == MEM_IMAGE,
     }
 }